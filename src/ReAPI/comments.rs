@@ -0,0 +1,292 @@
+//! Downloads the comment tree of a post, including the comments hidden behind "load more".
+use std::collections::HashMap;
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::Client;
+
+/// Reddit returns at most this many comments per "load more" request
+const MORE_CHILDREN_BATCH: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Comment {
+    pub id: String,
+    pub author: String,
+    pub body: String,
+    pub score: i64,
+    /// Unix timestamp (UTC)
+    pub created: f64,
+    /// Nesting level; top level comments are 0
+    pub depth: u32,
+    pub replies: Vec<Comment>,
+}
+
+impl Comment {
+    /// Replaces the authors of the comment and its replies with [super::NO_USERNAME]
+    pub(crate) fn anonymize(&mut self) {
+        self.author = super::NO_USERNAME.to_owned();
+        self.replies.iter_mut().for_each(Comment::anonymize);
+    }
+}
+
+/// The `data` of a comment (`t1` thing) as returned by the API, in comment trees and in listings
+#[derive(Debug, Deserialize)]
+pub(crate) struct CommentData {
+    /// Fullname (Example: t1_jj1aaaa)
+    pub name: String,
+    /// Fullname of the comment or post it replies to
+    #[serde(default)]
+    pub parent_id: String,
+    /// Only in comment trees
+    #[serde(default)]
+    pub depth: u32,
+    /// A listing of replies, or an empty string for comments without replies
+    #[serde(default)]
+    pub replies: Value,
+    #[serde(default = "super::deleted")]
+    pub author: String,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default)]
+    pub score: i64,
+    #[serde(default)]
+    pub created_utc: f64,
+    #[serde(default)]
+    pub permalink: String,
+    #[serde(default)]
+    pub subreddit_name_prefixed: String,
+    /// Only in listings (saved items, user history)
+    #[serde(default)]
+    pub link_title: String,
+    #[serde(default)]
+    pub link_permalink: String,
+}
+
+impl CommentData {
+    /// Parses the `data` of a comment, None (with a warning) if it is broken
+    pub(crate) fn parse(data: &Value) -> Option<CommentData> {
+        match CommentData::deserialize(data) {
+            Ok(data) => Some(data),
+            Err(error) => {
+                warn!("Failed to parse comment: {error}");
+                None
+            }
+        }
+    }
+}
+
+/// How much of the discussion to download
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommentOptions {
+    /// Deepest nesting level to keep (0 only keeps top level comments)
+    pub depth: Option<u32>,
+    /// Maximum number of comments per post
+    pub limit: Option<usize>,
+}
+
+/// A comment before it is placed in the tree
+struct FlatComment {
+    parent: String,
+    comment: Comment,
+}
+
+/// Downloads the comments of the post at `permalink` (Example: /r/redditdev/comments/abc/title/)
+pub async fn download_comments(client: &Client, permalink: &str, options: CommentOptions) -> Vec<Comment> {
+    info!("Getting comments");
+
+    let mut url = client.reddit(&format!("{}.json?raw_json=1&limit=500", permalink.trim_end_matches('/')));
+    if let Some(depth) = options.depth {
+        // Reddit counts the levels starting at 1
+        url.push_str(&format!("&depth={}", depth + 1));
+    }
+
+    let Some(listing) = get_json(client, url).await else {
+        return vec![];
+    };
+
+    // The first listing is the post itself, the second one the comments
+    let Some(link_id) = listing[0]["data"]["children"][0]["data"]["name"].as_str().map(str::to_owned) else {
+        warn!("Could not find the post in the comment listing");
+        return vec![];
+    };
+
+    let mut comments = vec![];
+    let mut more = vec![];
+    flatten(&listing[1]["data"]["children"], &mut comments, &mut more);
+
+    // Expand "load more" until everything (or the limit) is loaded
+    while !more.is_empty() && !limit_reached(&comments, options) {
+        let batch: Vec<String> = more.drain(..more.len().min(MORE_CHILDREN_BATCH)).collect();
+        debug!("Loading {} more comment(s)", batch.len());
+
+        let url = client.reddit(&format!(
+            "/api/morechildren.json?api_type=json&raw_json=1&link_id={link_id}&children={}",
+            batch.join(",")
+        ));
+        let Some(response) = get_json(client, url).await else {
+            break;
+        };
+
+        flatten(&response["json"]["data"]["things"], &mut comments, &mut more);
+    }
+
+    if let Some(depth) = options.depth {
+        comments.retain(|flat| flat.comment.depth <= depth);
+    }
+    if let Some(limit) = options.limit {
+        comments.truncate(limit);
+    }
+
+    build_tree(comments, &link_id)
+}
+
+async fn get_json(client: &Client, url: String) -> Option<Value> {
+    let response = match client.send(client.get(url)).await {
+        Ok(response) => response,
+        Err(error) => {
+            warn!("Failed to send HTTP request: {error}");
+            return None;
+        }
+    };
+
+    match serde_json::from_str(response.text().await.unwrap_or_default().as_str()) {
+        Ok(json) => Some(json),
+        Err(error) => {
+            warn!("Failed to parse comments: {error}");
+            None
+        }
+    }
+}
+
+fn limit_reached(comments: &[FlatComment], options: CommentOptions) -> bool {
+    options.limit.is_some_and(|limit| comments.len() >= limit)
+}
+
+/// Collects the comments of a listing (and their replies) in order, plus the ids hidden behind "load more"
+fn flatten(children: &Value, comments: &mut Vec<FlatComment>, more: &mut Vec<String>) {
+    for child in children.as_array().into_iter().flatten() {
+        let data = &child["data"];
+
+        match child["kind"].as_str() {
+            Some("t1") => {
+                let Some(data) = CommentData::parse(data) else {
+                    continue;
+                };
+
+                comments.push(FlatComment {
+                    parent: data.parent_id,
+                    comment: Comment {
+                        // The id without the t1_ prefix
+                        id: data.name.trim_start_matches("t1_").to_owned(),
+                        author: data.author,
+                        body: data.body,
+                        score: data.score,
+                        created: data.created_utc,
+                        depth: data.depth,
+                        replies: vec![],
+                    },
+                });
+
+                // Comments without replies have an empty string instead of a listing
+                flatten(&data.replies["data"]["children"], comments, more);
+            }
+            Some("more") => {
+                let ids: Vec<String> = data["children"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|id| id.as_str())
+                    .map(str::to_owned)
+                    .collect();
+
+                // "Continue this thread" links have no ids and would need a request per thread
+                if ids.is_empty() {
+                    debug!("Skipping continued thread below {}", data["parent_id"]);
+                }
+                more.extend(ids);
+            }
+            kind => debug!("Skipping comment listing item of kind {kind:?}"),
+        }
+    }
+}
+
+/// Nests the comments below their parents, keeping the order they were loaded in
+fn build_tree(comments: Vec<FlatComment>, link_id: &str) -> Vec<Comment> {
+    let mut children: HashMap<String, Vec<Comment>> = HashMap::new();
+    for flat in comments {
+        children.entry(flat.parent).or_default().push(flat.comment);
+    }
+
+    attach(&mut children, link_id)
+}
+
+fn attach(children: &mut HashMap<String, Vec<Comment>>, parent: &str) -> Vec<Comment> {
+    let mut comments = children.remove(parent).unwrap_or_default();
+    for comment in &mut comments {
+        comment.replies = attach(children, &format!("t1_{}", comment.id));
+    }
+
+    comments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CommentOptions;
+
+    const PERMALINK: &str = "/r/rexitTest/comments/13a9rn6/testing_rexit/";
+
+    #[tokio::test]
+    async fn download_comments() {
+        let (client, _server) = super::super::mock::mock_client().await;
+
+        let comments = super::download_comments(&client, PERMALINK, CommentOptions::default()).await;
+
+        // The two comments behind "load more" are fetched and nested as well
+        assert_eq!(comments.len(), 2);
+        assert_eq!(comments[0].body, "First!");
+        assert_eq!(comments[0].replies[0].author, "otherUser");
+        assert_eq!(comments[1].body, "Loaded later");
+        assert_eq!(comments[1].replies[0].body, "Reply loaded later");
+        assert_eq!(comments[1].replies[0].depth, 1);
+    }
+
+    #[test]
+    fn flatten() {
+        let children = serde_json::json!([
+            { "kind": "t1", "data": { "name": "t1_a", "parent_id": "t3_x", "body": "[removed]", "replies": "" } },
+            { "kind": "t1", "data": { "body": "No name" } }
+        ]);
+        let (mut comments, mut more) = (vec![], vec![]);
+
+        super::flatten(&children, &mut comments, &mut more);
+
+        // Deleted comments have no author, broken ones are skipped
+        assert_eq!(comments.len(), 1);
+        assert_eq!(comments[0].comment.id, "a");
+        assert_eq!(comments[0].comment.author, "[deleted]");
+        assert_eq!(comments[0].parent, "t3_x");
+    }
+
+    #[tokio::test]
+    async fn download_comments_limited() {
+        let (client, _server) = super::super::mock::mock_client().await;
+
+        let options = CommentOptions {
+            depth: Some(0),
+            limit: None,
+        };
+        let comments = super::download_comments(&client, PERMALINK, options).await;
+        assert_eq!(comments.len(), 2);
+        assert!(comments.iter().all(|comment| comment.replies.is_empty()));
+
+        let options = CommentOptions {
+            depth: None,
+            limit: Some(2),
+        };
+        let comments = super::download_comments(&client, PERMALINK, options).await;
+        assert_eq!(comments.len(), 1);
+        assert_eq!(comments[0].replies.len(), 1);
+    }
+}
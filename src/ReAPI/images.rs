@@ -1,9 +1,9 @@
 use super::Client;
-use crate::{exit, image_log};
-use console::style;
-use log::{error, info};
+use crate::media_store::{self, MediaEntry};
+use crate::progress;
+use log::{debug, info, warn};
 use serde::Serialize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use url::Url;
 
 #[derive(std::hash::Hash, Clone, Debug, Serialize)]
@@ -34,95 +34,333 @@ impl Image {
     }
 }
 
-/// Gets images from a mxc:// URL as per [SPEC](https://spec.matrix.org/v1.6/client-server-api/#get_matrixmediav3downloadservernamemediaid)
-pub async fn get_image(client: &Client, url: String, out: PathBuf, path: &std::path::Path, redact: bool) {
-    let mut url = url;
-    let mut id: Option<String> = None;
+/// MIME type to file extension of the media types seen in chats and posts
+const EXTENSIONS: [(&str, &str); 24] = [
+    ("image/jpeg", "jpeg"),
+    ("image/jpg", "jpeg"),
+    ("image/png", "png"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+    ("image/bmp", "bmp"),
+    ("image/svg+xml", "svg"),
+    ("image/heic", "heic"),
+    ("image/avif", "avif"),
+    ("video/mp4", "mp4"),
+    ("video/webm", "webm"),
+    ("video/quicktime", "mov"),
+    ("audio/mpeg", "mp3"),
+    ("audio/mp4", "m4a"),
+    ("audio/ogg", "ogg"),
+    ("audio/webm", "weba"),
+    ("audio/wav", "wav"),
+    ("audio/aac", "aac"),
+    ("application/pdf", "pdf"),
+    ("application/zip", "zip"),
+    ("application/json", "json"),
+    ("text/plain", "txt"),
+    ("text/csv", "csv"),
+    ("application/octet-stream", "bin"),
+];
 
-    if image_log::check_image_log(out.clone(), url.clone()) {
+/// Gets images from a mxc:// URL as per [SPEC](https://spec.matrix.org/v1.6/client-server-api/#get_matrixmediav3downloadservernamemediaid) (or any other URL) into the media store.
+///
+/// Returns the stored file, or None if the download failed.
+pub async fn get_image(client: &Client, url: String, out: PathBuf, path: &Path, redact: bool) -> Option<MediaEntry> {
+    get_media(client, url, None, None, out, path, redact).await
+}
+
+/// Gets any media (image, video, audio or file) into the media store.
+///
+/// The file extension is picked by [media_path]: the Content-Type of the response wins over a `format` query parameter,
+/// which wins over the extension of the URL. Without any of them the `mimetype` of the event or the original `filename`
+/// is used, falling back to `.bin`.
+pub async fn get_media(
+    client: &Client,
+    url: String,
+    mimetype: Option<&str>,
+    filename: Option<&str>,
+    out: PathBuf,
+    path: &Path,
+    redact: bool,
+) -> Option<MediaEntry> {
+    if let Some(entry) = media_store::lookup(&out, &url) {
         // Image was already downloaded
         info!("Image was already downloaded; Skipping");
-        return;
+        progress::media_skipped();
+        return Some(entry);
     }
 
     // Handle redaction
     if redact  {
       info!(target: "get_image", "Getting image: [REDACTED]");
     } else {
-      info!(target: "get_image", "Getting image: {}...", &url[0..30]);
+      info!(target: "get_image", "Getting image: {}...", url.chars().take(30).collect::<String>());
     }
 
-    image_log::write_image_log(out, url.clone());
-
-    if url.starts_with("mxc") {
+    let (download_url, id) = if url.starts_with("mxc") {
         // Matrix images
-        (url, id) = parse_matrix_image_url(url.as_str());
-        let data = client.reqwest_client.get(url.clone()).send().await.unwrap();
-        let path = path
-            .join(id.unwrap())
-            .with_extension(get_image_extension(data.headers()));
-
-        std::fs::write(path, data.bytes().await.unwrap().to_vec()).unwrap();
+        parse_matrix_image_url(client, url.as_str())
     } else {
         // Litteraly any other image
         // Parse the image url to get the ID
-        id = Some(Url::parse(&url).unwrap().path().to_string());
-        id = Some(id.unwrap().replace("/", ""));
+        let Ok(parsed) = Url::parse(&url) else {
+            warn!("Skipping media with an invalid URL");
+            progress::media_failed();
+            return None;
+        };
+        (url.clone(), parsed.path().replace('/', ""))
+    };
+
+    let Some((content_type, data)) = fetch(client, download_url).await else {
+        progress::media_failed();
+        return None;
+    };
+    progress::media_downloaded(data.len());
+
+    let path = media_path(&path.join(&id), &url, mimetype, content_type.as_deref(), filename);
+
+    Some(media_store::store(&out, &url, &data, &path))
+}
 
-        let data = client.reqwest_client.get(url.clone()).send().await.unwrap();
-        let path = path.join(id.unwrap());
+/// Downloads a broken file of the media store again, to the path it was stored at
+pub async fn repair_media(client: &Client, entry: &MediaEntry, out: &Path) -> Option<MediaEntry> {
+    let download_url = if entry.source.starts_with("mxc") {
+        parse_matrix_image_url(client, &entry.source).0
+    } else {
+        entry.source.clone()
+    };
+
+    let (content_type, data) = fetch(client, download_url).await?;
+
+    // Forget the broken file so the new one takes its place; files saved with the wrong extension get the right one
+    let _ = std::fs::remove_file(out.join(&entry.path));
+    media_store::remove(out, entry);
+
+    let path = media_path(&out.join(&entry.path), &entry.source, None, content_type.as_deref(), None);
+    Some(media_store::store(out, &entry.source, &data, &path))
+}
+
+/// Downloads a file, returning its Content-Type and data; None unless the download was complete
+pub(crate) async fn fetch(client: &Client, url: String) -> Option<(Option<String>, Vec<u8>)> {
+    let response = match client.send(client.get(url)).await {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => {
+            warn!("Failed to download image: HTTP {}", response.status());
+            return None;
+        }
+        Err(error) => {
+            warn!("Failed to download image: {error}");
+            return None;
+        }
+    };
 
-        std::fs::write(path, data.bytes().await.unwrap().to_vec()).unwrap();
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_owned());
+
+    // Only complete downloads make it into the store
+    let expected_length = response.content_length();
+    let Ok(data) = response.bytes().await else {
+        warn!("Failed to download image: connection closed");
+        return None;
+    };
+    if expected_length.is_some_and(|length| length != data.len() as u64) {
+        warn!("Failed to download image: expected {} bytes, got {}", expected_length.unwrap(), data.len());
+        return None;
     }
+
+    Some((content_type, data.to_vec()))
 }
 
-fn parse_matrix_image_url(url: &str) -> (String, Option<String>) {
+fn parse_matrix_image_url(client: &Client, url: &str) -> (String, String) {
     let url = reqwest::Url::parse(url).unwrap(); // I assume that all urls given to this function are valid
 
-    let output_url =
-        reqwest::Url::parse("https://matrix.redditspace.com/_matrix/media/r0/download/reddit.com/")
-            .unwrap();
-
     let id = url.path_segments().unwrap().next().unwrap();
 
-    let output_url = output_url.join(id).unwrap();
+    let output_url = client.matrix(&format!("/_matrix/media/r0/download/reddit.com/{id}"));
+
+    (output_url, id.to_string())
+}
+
+/// Where a download is saved: the type the server sent wins over a `format` query parameter
+/// (Example: https://preview.redd.it/<id>.gif?format=mp4 is an MP4), which wins over the extension in the URL.
+/// Files with neither (like mxc URIs) use the `mimetype` of the event or the original `filename`.
+fn media_path(path: &Path, url: &str, mimetype: Option<&str>, content_type: Option<&str>, filename: Option<&str>) -> PathBuf {
+    let known = |extension: &String| extension != "bin" && EXTENSIONS.iter().any(|(_, known)| known == extension);
+
+    let served = content_type.map(|content_type| get_extension(Some(content_type), None)).filter(known);
+    let format = Url::parse(url)
+        .ok()
+        .and_then(|url| url.query_pairs().find(|(key, _)| key == "format").map(|(_, value)| value.to_lowercase()))
+        .filter(known);
 
-    (output_url.to_string(), Some(id.to_string()))
+    match served.or(format) {
+        Some(extension) => path.with_extension(extension),
+        None if path.extension().is_some() => path.to_path_buf(),
+        None => path.with_extension(get_extension(mimetype.or(content_type), filename)),
+    }
 }
 
-fn get_image_extension(headers: &reqwest::header::HeaderMap) -> String {
-    let mut extension: Option<String> = None;
+/// Picks the file extension for a MIME type, using the original filename for unknown types
+pub(crate) fn get_extension(mimetype: Option<&str>, filename: Option<&str>) -> String {
+    // Strip parameters like "; charset=utf-8"
+    let mimetype = mimetype
+        .and_then(|mimetype| mimetype.split(';').next())
+        .map(|mimetype| mimetype.trim().to_lowercase());
 
-    // Iterate over headers to find content-type
-    for (header_name, header_value) in headers {
-        if header_name.as_str() != "content-type" {
-            continue;
+    if let Some(mimetype) = mimetype {
+        if let Some((_, extension)) = EXTENSIONS.iter().find(|(known, _)| *known == mimetype) {
+            return extension.to_string();
         }
-        let file_type = header_value.to_str().unwrap().to_string();
-
-        let mut file_type = file_type.split("/");
-
-        extension = match file_type.nth(1).unwrap() {
-            "jpeg" => Some("jpeg".to_string()),
-            "png" => Some("png".to_string()),
-            "gif" => Some("gif".to_string()),
-            _ => {
-                println!("{}", style("Failed to read image type").red().bold());
-                exit!(0);
-            }
-        };
+        debug!("Unknown media type: {mimetype}");
+    }
+
+    let from_filename = filename
+        .and_then(|filename| Path::new(filename).extension())
+        .and_then(|extension| extension.to_str())
+        .filter(|extension| extension.chars().all(|char| char.is_ascii_alphanumeric()));
+
+    match from_filename {
+        Some(extension) => extension.to_lowercase(),
+        None => "bin".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn get_image() {
+        let (client, _server) = super::super::mock::mock_client().await;
+        let out = tempfile::tempdir().unwrap();
+        let out = out.path();
+
+        let entry = super::get_image(&client, "mxc://reddit.com/mockimage0001".to_owned(), out.to_path_buf(), out, false)
+            .await
+            .unwrap();
+
+        assert_eq!(entry.path, Path::new("mockimage0001.png"));
+        assert_eq!(std::fs::read(out.join(&entry.path)).unwrap(), std::fs::read(super::super::mock::media("image.png")).unwrap());
+
+        // Known sources are not downloaded again
+        let again = super::get_image(&client, "mxc://reddit.com/mockimage0001".to_owned(), out.to_path_buf(), out, false)
+            .await
+            .unwrap();
+        assert_eq!(again.sha256, entry.sha256);
+    }
+
+    #[tokio::test]
+    async fn get_media() {
+        let (client, _server) = super::super::mock::mock_client().await;
+        let out = tempfile::tempdir().unwrap();
+        let out = out.path();
+
+        let entry = super::get_media(&client, "mxc://reddit.com/mockvideo0001".to_owned(), Some("video/mp4"), Some("clip.mp4"), out.to_path_buf(), out, false)
+            .await
+            .unwrap();
+
+        assert_eq!(entry.path, Path::new("mockvideo0001.mp4"));
+    }
+
+    #[tokio::test]
+    async fn repair_media() {
+        let (client, _server) = super::super::mock::mock_client().await;
+        let out = tempfile::tempdir().unwrap();
+        let out = out.path();
+
+        let entry = super::get_image(&client, "mxc://reddit.com/mockimage0001".to_owned(), out.to_path_buf(), out, false)
+            .await
+            .unwrap();
+        std::fs::write(out.join(&entry.path), b"truncated").unwrap();
+        assert!(crate::media_store::verify(out, &entry).is_err());
+
+        let repaired = super::repair_media(&client, &entry, out).await.unwrap();
+
+        assert_eq!(repaired.path, entry.path);
+        assert_eq!(crate::media_store::verify(out, &repaired), Ok(()));
     }
 
-    if extension.is_none() {
-        println!(
-            "{}",
-            style("Error: Something failed reading the image type")
-                .red()
-                .bold()
-        );
-        error!("Something failed reading the image type");
-        exit!(0);
+    #[test]
+    fn get_extension() {
+        assert_eq!(super::get_extension(Some("image/png"), None), "png");
+        assert_eq!(super::get_extension(Some("Text/Plain; charset=utf-8"), None), "txt");
+        assert_eq!(super::get_extension(Some("audio/ogg"), Some("Voice message")), "ogg");
+        // Unknown types keep the extension of the original file
+        assert_eq!(super::get_extension(Some("application/x-rexit"), Some("notes.XYZ")), "xyz");
+        assert_eq!(super::get_extension(None, Some("report.pdf")), "pdf");
+        assert_eq!(super::get_extension(Some("application/x-rexit"), Some("no extension")), "bin");
+        assert_eq!(super::get_extension(None, None), "bin");
     }
 
-    return extension.unwrap();
+    #[tokio::test]
+    async fn format_overrides_extension() {
+        let (client, server) = super::super::mock::mock_client().await;
+        let out = tempfile::tempdir().unwrap();
+        let out = out.path();
+
+        // The b2 gallery item: an animated image served as MP4 under a .gif name
+        let url = format!("{}/b2.gif?format=mp4&s=x", server.uri());
+        let entry = super::get_image(&client, url.clone(), out.to_path_buf(), out, false).await.unwrap();
+
+        assert_eq!(entry.path, Path::new("b2.mp4"));
+        assert_eq!(crate::media_store::verify(out, &entry), Ok(()));
+
+        // Files stored with the wrong extension get the right one when repaired
+        let out = tempfile::tempdir().unwrap();
+        let out = out.path();
+        let wrong = crate::media_store::store(out, "wrong", b"broken", &out.join("wrong.gif"));
+        let wrong = crate::media_store::MediaEntry { source: url, ..wrong };
+        let repaired = super::repair_media(&client, &wrong, out).await.unwrap();
+        assert_eq!(repaired.path, Path::new("wrong.mp4"));
+        assert_eq!(crate::media_store::verify(out, &repaired), Ok(()));
+
+        // The next verify reads the manifest from disk and only finds the repaired file
+        crate::media_store::reload(out);
+        let entries = crate::media_store::entries(out);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, Path::new("wrong.mp4"));
+        assert_eq!(crate::media_store::verify(out, &entries[0]), Ok(()));
+    }
+
+    #[test]
+    fn media_path() {
+        let path = Path::new("images/id.gif");
+
+        assert_eq!(super::media_path(path, "https://preview.redd.it/id.gif?format=mp4", None, None, None), Path::new("images/id.mp4"));
+        assert_eq!(super::media_path(path, "https://i.redd.it/id.gif", None, Some("video/mp4"), None), Path::new("images/id.mp4"));
+        // Unknown formats (Example: pjpg) and generic types keep the extension of the URL
+        assert_eq!(super::media_path(path, "https://preview.redd.it/id.gif?format=pjpg", None, Some("application/octet-stream"), None), path);
+        assert_eq!(super::media_path(Path::new("id"), "mxc://reddit.com/id", Some("image/png"), None, None), Path::new("id.png"));
+    }
+
+    #[tokio::test]
+    async fn failed_download_is_not_stored() {
+        let (client, _server) = super::super::mock::mock_client().await;
+        let out = tempfile::tempdir().unwrap();
+        let out = out.path();
+
+        let entry = super::get_image(&client, "mxc://reddit.com/missing".to_owned(), out.to_path_buf(), out, false).await;
+
+        assert!(entry.is_none());
+        assert!(crate::media_store::lookup(out, "mxc://reddit.com/missing").is_none());
+    }
+
+    #[tokio::test]
+    async fn odd_urls_are_skipped() {
+        let (client, server) = super::super::mock::mock_client().await;
+        let out = tempfile::tempdir().unwrap();
+        let out = out.path();
+
+        // Relative URLs can not be parsed
+        assert!(super::get_image(&client, "/relative.png".to_owned(), out.to_path_buf(), out, false).await.is_none());
+
+        // The logged start of the URL ends inside the é
+        let prefix = format!("{}/", server.uri());
+        let url = format!("{prefix}{}é.png", "a".repeat(29 - prefix.len()));
+        assert!(!url.is_char_boundary(30));
+        assert!(super::get_image(&client, url, out.to_path_buf(), out, false).await.is_none());
+    }
 }
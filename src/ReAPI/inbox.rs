@@ -0,0 +1,183 @@
+//! Downloads the legacy private messages (inbox and sent), which are separate from the Matrix chats.
+use std::collections::{HashMap, HashSet};
+
+use chrono::{TimeZone, Utc};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{listing, Client, Content, Fetched, Message, Room};
+
+/// The message boxes to download; unread messages are also in the inbox but are listed for completeness
+const BOXES: [&str; 3] = ["inbox", "sent", "unread"];
+
+/// The `data` of a private message as returned by the API
+#[derive(Debug, Clone, Deserialize)]
+struct PrivateMessage {
+    name: String,
+    /// Fullname of the first message of the conversation; None for the first message itself
+    first_message_name: Option<String>,
+    #[serde(default)]
+    author: Option<String>,
+    #[serde(default)]
+    subject: String,
+    #[serde(default)]
+    body: String,
+    #[serde(default)]
+    created_utc: f64,
+}
+
+/// Downloads all private messages, grouped into one [Room] per conversation (oldest conversation first).
+///
+/// If a page can not be fetched the conversations of the messages before it are kept along with the error.
+/// With `no_usernames` the authors are replaced with [super::NO_USERNAME].
+pub async fn download_inbox(client: &Client, no_usernames: bool) -> Fetched<Room> {
+    info!("Getting private messages");
+
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut messages: Vec<PrivateMessage> = vec![];
+    let mut errors: Vec<String> = vec![];
+
+    for message_box in BOXES {
+        let mut after_token = String::new();
+
+        loop {
+            let path = format!("/message/{message_box}.json?after={after_token}&limit=100");
+            let listing = match listing::get_page(client, &path).await {
+                Ok(listing) => listing,
+                Err(error) => {
+                    warn!("Stopping the {message_box} box: {error}");
+                    errors.push(format!("{message_box}: {error}"));
+                    break;
+                }
+            };
+
+            collect(&listing["data"]["children"], &mut seen, &mut messages);
+
+            match listing["data"]["after"].as_str() {
+                Some(after) => after_token = after.to_owned(),
+                None => break,
+            }
+        }
+    }
+
+    info!("Found {} private message(s) in {:?}", messages.len(), BOXES);
+
+    if no_usernames {
+        messages.iter_mut().for_each(|message| message.author = Some(super::NO_USERNAME.to_owned()));
+    }
+
+    Fetched {
+        items: threads(messages),
+        error: (!errors.is_empty()).then(|| errors.join("; ")),
+    }
+}
+
+/// Collects the messages of a listing and their replies, skipping ones already seen in another box
+fn collect(children: &Value, seen: &mut HashSet<String>, messages: &mut Vec<PrivateMessage>) {
+    for child in children.as_array().into_iter().flatten() {
+        // Comment replies and mentions are in the inbox too
+        if child["kind"].as_str() != Some("t4") {
+            debug!("Skipping inbox item of kind {}", child["kind"]);
+            continue;
+        }
+
+        let Ok(message) = PrivateMessage::deserialize(&child["data"]) else {
+            debug!("Skipping unparsable private message");
+            continue;
+        };
+
+        // Replies are nested below the first message; they are empty strings if there are none
+        collect(&child["data"]["replies"]["data"]["children"], seen, messages);
+
+        if seen.insert(message.name.clone()) {
+            messages.push(message);
+        }
+    }
+}
+
+/// Groups messages into conversations by their first message
+fn threads(messages: Vec<PrivateMessage>) -> Vec<Room> {
+    let mut conversations: HashMap<String, Vec<PrivateMessage>> = HashMap::new();
+    for message in messages {
+        let id = message.first_message_name.clone().unwrap_or(message.name.clone());
+        conversations.entry(id).or_default().push(message);
+    }
+
+    let mut rooms: Vec<(f64, Room)> = conversations
+        .into_iter()
+        .map(|(id, mut messages)| {
+            messages.sort_by(|a, b| a.created_utc.total_cmp(&b.created_utc));
+            let started = messages[0].created_utc;
+
+            let room = Room {
+                id,
+                name: Some(messages[0].subject.clone()),
+                messages: Some(messages.into_iter().map(to_message).collect()),
+            };
+            (started, room)
+        })
+        .collect();
+
+    rooms.sort_by(|a, b| a.0.total_cmp(&b.0));
+    rooms.into_iter().map(|(_, room)| room).collect()
+}
+
+fn to_message(message: PrivateMessage) -> Message {
+    Message {
+        // Messages from subreddits have no author
+        author: message.author.unwrap_or("[unknown]".to_owned()),
+        timestamp: Utc
+            .timestamp_opt(message.created_utc as i64, 0)
+            .single()
+            .unwrap_or_default(),
+        content: Content::Message(message.body),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ReAPI::Content;
+
+    #[tokio::test]
+    async fn download_inbox() {
+        let (client, _server) = super::super::mock::mock_client().await;
+
+        let rooms = super::download_inbox(&client, false).await;
+        assert_eq!(rooms.error, None);
+        let rooms = rooms.items;
+
+        // The reply from the sent box joins the conversation of the inbox message, the comment reply is skipped
+        assert_eq!(rooms.len(), 2);
+        assert_eq!(rooms[0].id, "t4_1aaaaa");
+        assert_eq!(rooms[0].name.as_deref(), Some("Hello"));
+
+        let messages = rooms[0].messages();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].author, "otherUser");
+        assert_eq!(messages[1].author, "rexitTest");
+        let Content::Message(text) = &messages[2].content else {
+            panic!("Expected a text message");
+        };
+        assert_eq!(text, "Nested reply");
+
+        assert_eq!(rooms[1].messages().len(), 1);
+
+        let rooms = super::download_inbox(&client, true).await.items;
+        assert!(rooms.iter().flat_map(|room| room.messages()).all(|message| message.author == "N/A"));
+    }
+
+    #[tokio::test]
+    async fn download_inbox_failing() {
+        // A server that knows no message boxes
+        let server = wiremock::MockServer::start().await;
+        let mut client = super::super::new_client(false);
+        client.set_reddit_url(&server.uri());
+
+        let rooms = super::download_inbox(&client, false).await;
+
+        assert!(rooms.items.is_empty());
+        let error = rooms.error.unwrap();
+        assert!(error.starts_with("inbox: ") && error.contains("404"));
+    }
+}
@@ -0,0 +1,329 @@
+//! Sorting, filtering and paging of post listings (subreddits, users and searches).
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use log::{debug, info, warn};
+use serde_json::Value;
+
+use super::posts::{self, PostOptions};
+use super::saved_posts::{SavedComment, SavedItem};
+use super::{Client, Fetched, Post};
+use crate::progress;
+
+/// Order of a listing
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum Sort {
+    #[default]
+    Hot,
+    New,
+    Top,
+    Rising,
+    Controversial,
+}
+
+/// Order of search results
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum SearchSort {
+    #[default]
+    Relevance,
+    Hot,
+    Top,
+    New,
+    /// Most comments first
+    Comments,
+}
+
+/// Time window of the top and controversial listings
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum TimeWindow {
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+    All,
+}
+
+/// An order posts can be requested in
+pub trait ListingSort: Copy + PartialEq {
+    /// Value in the URL
+    fn as_str(self) -> &'static str;
+    /// Whether the newest posts come first
+    fn newest_first(self) -> bool;
+    /// Whether Reddit applies the time window to this order
+    fn has_time_window(self) -> bool;
+}
+
+/// Which posts of a listing to download
+#[derive(Debug, Clone, Default)]
+pub struct ListingOptions<S = Sort> {
+    pub sort: S,
+    /// Only used for sorts with a time window (e.g. top)
+    pub time: Option<TimeWindow>,
+    /// Maximum number of posts
+    pub limit: Option<usize>,
+    /// Only posts created at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only posts created before this time
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Reddit returns at most this many posts per page
+const PAGE_SIZE: usize = 100;
+
+impl ListingSort for Sort {
+    fn as_str(self) -> &'static str {
+        match self {
+            Sort::Hot => "hot",
+            Sort::New => "new",
+            Sort::Top => "top",
+            Sort::Rising => "rising",
+            Sort::Controversial => "controversial",
+        }
+    }
+
+    fn newest_first(self) -> bool {
+        self == Sort::New
+    }
+
+    fn has_time_window(self) -> bool {
+        matches!(self, Sort::Top | Sort::Controversial)
+    }
+}
+
+impl ListingSort for SearchSort {
+    fn as_str(self) -> &'static str {
+        match self {
+            SearchSort::Relevance => "relevance",
+            SearchSort::Hot => "hot",
+            SearchSort::Top => "top",
+            SearchSort::New => "new",
+            SearchSort::Comments => "comments",
+        }
+    }
+
+    fn newest_first(self) -> bool {
+        self == SearchSort::New
+    }
+
+    fn has_time_window(self) -> bool {
+        !matches!(self, SearchSort::Hot | SearchSort::New)
+    }
+}
+
+impl TimeWindow {
+    fn as_str(self) -> &'static str {
+        match self {
+            TimeWindow::Hour => "hour",
+            TimeWindow::Day => "day",
+            TimeWindow::Week => "week",
+            TimeWindow::Month => "month",
+            TimeWindow::Year => "year",
+            TimeWindow::All => "all",
+        }
+    }
+}
+
+impl<S: ListingSort> ListingOptions<S> {
+    /// Path of a page of the listing below `base` (Example: /r/redditdev)
+    pub(crate) fn path(&self, base: &str, after: &str) -> String {
+        format!("{base}/{}.json?{}", self.sort.as_str(), self.query(after))
+    }
+
+    /// Query parameters for paging, the page size and the time window
+    pub(crate) fn query(&self, after: &str) -> String {
+        let page_size = self.limit.unwrap_or(PAGE_SIZE).clamp(1, PAGE_SIZE);
+        let mut query = format!("after={after}&limit={page_size}");
+
+        if let Some(time) = self.time {
+            if self.sort.has_time_window() {
+                query.push_str(&format!("&t={}", time.as_str()));
+            }
+        }
+
+        query
+    }
+
+    /// Whether a post created at `created` (Unix timestamp) is inside the date range
+    pub(crate) fn in_range(&self, created: f64) -> bool {
+        let created = created as i64;
+        self.since.is_none_or(|since| created >= since.timestamp())
+            && self.until.is_none_or(|until| created < until.timestamp())
+    }
+
+    /// Whether no later post can be in the date range, as the newest posts come first
+    pub(crate) fn past_range(&self, created: f64) -> bool {
+        self.sort.newest_first() && self.since.is_some_and(|since| (created as i64) < since.timestamp())
+    }
+
+    pub(crate) fn limit_reached(&self, count: usize) -> bool {
+        self.limit.is_some_and(|limit| count >= limit)
+    }
+}
+
+/// Downloads the posts of a listing, see [download_items]; comments in the listing are skipped
+pub(crate) async fn download_posts<S: ListingSort>(
+    client: &Client,
+    listing: &ListingOptions<S>,
+    page: impl Fn(&str) -> String,
+    options: PostOptions,
+    out: &Path,
+    folder: &Path,
+    redact: bool,
+) -> Fetched<Post> {
+    download_items(client, listing, page, options, out, folder, redact)
+        .await
+        .map(|item| match item {
+            SavedItem::Post(post) => Some(post),
+            SavedItem::Comment(_) => None,
+        })
+}
+
+/// Downloads the posts and comments of a listing, following the pages until the end, the limit or the date range is reached.
+///
+/// `page` returns the path of the page after the given fullname (empty for the first page).
+/// The media of posts is saved into `folder`.
+/// If a page can not be fetched the items of the pages before it are kept along with the error.
+pub(crate) async fn download_items<S: ListingSort>(
+    client: &Client,
+    listing: &ListingOptions<S>,
+    page: impl Fn(&str) -> String,
+    options: PostOptions,
+    out: &Path,
+    folder: &Path,
+    redact: bool,
+) -> Fetched<SavedItem> {
+    let mut after_token = String::new();
+    let mut item_list: Vec<SavedItem> = Vec::<SavedItem>::new();
+    let mut error = None;
+
+    'pages: loop {
+        let listing_page = match get_page(client, &page(&after_token)).await {
+            Ok(listing_page) => listing_page,
+            Err(message) => {
+                warn!("Stopping the listing after {} item(s): {message}", item_list.len());
+                error = Some(message);
+                break;
+            }
+        };
+
+        // Iterates over all items in the response array
+        for item in listing_page["data"]["children"].as_array().into_iter().flatten() {
+            if listing.limit_reached(item_list.len()) {
+                break 'pages;
+            }
+
+            // Filter by date before downloading anything
+            let data = &item["data"];
+            let created = data["created_utc"].as_f64().unwrap_or_default();
+            if listing.past_range(created) {
+                debug!("Reached items before --since");
+                break 'pages;
+            }
+            if !listing.in_range(created) {
+                continue;
+            }
+
+            match item["kind"].as_str() {
+                Some("t1") => {
+                    if let Some(mut comment) = SavedComment::from_data(data) {
+                        if options.no_usernames {
+                            comment.author = super::NO_USERNAME.to_owned();
+                        }
+                        item_list.push(SavedItem::Comment(comment));
+                        progress::items(1);
+                    }
+                }
+                Some("t3") => {
+                    if let Some(post) = posts::download_post(client, data, options, out, folder, redact).await {
+                        item_list.push(SavedItem::Post(post));
+                        progress::items(1);
+                    }
+                }
+                kind => warn!("Skipping listing item of kind {kind:?}"),
+            }
+        }
+        match listing_page["data"]["after"].as_str() {
+            Some(after) => after_token = after.to_owned(),
+            None => break,
+        }
+    }
+
+    info!("Got {} item(s)", item_list.len());
+    Fetched { items: item_list, error }
+}
+
+/// Requests a page of a listing; error bodies (Example: a banned or private subreddit) are errors
+pub(crate) async fn get_page(client: &Client, path: &str) -> Result<Value, String> {
+    let response = client
+        .send(client.get(client.reddit(path)))
+        .await
+        .map_err(|error| format!("Failed to send HTTP request: {error}"))?;
+    let status = response.status();
+
+    let text = response.text().await.map_err(|error| format!("Failed to read listing: {error}"))?;
+    let listing_page: Value = serde_json::from_str(&text).map_err(|error| format!("Failed to parse listing ({status}): {error}"))?;
+
+    if !listing_page["data"]["children"].is_array() {
+        let message = listing_page["message"].as_str().or(listing_page["reason"].as_str()).unwrap_or("no listing in the response");
+        return Err(format!("Reddit answered {status}: {message}"));
+    }
+
+    Ok(listing_page)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{ListingOptions, SearchSort, Sort, TimeWindow};
+
+    #[test]
+    fn path() {
+        let options: ListingOptions = ListingOptions::default();
+        assert_eq!(options.path("/r/rexitTest", ""), "/r/rexitTest/hot.json?after=&limit=100");
+
+        let options = ListingOptions {
+            sort: Sort::Top,
+            time: Some(TimeWindow::Week),
+            limit: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(options.path("/r/rexitTest", "t3_a"), "/r/rexitTest/top.json?after=t3_a&limit=10&t=week");
+
+        // The time window only applies to top and controversial
+        let options = ListingOptions {
+            sort: Sort::New,
+            time: Some(TimeWindow::Week),
+            ..Default::default()
+        };
+        assert_eq!(options.path("/r/rexitTest", ""), "/r/rexitTest/new.json?after=&limit=100");
+
+        // Search results sorted by relevance use it
+        let options = ListingOptions {
+            sort: SearchSort::Relevance,
+            time: Some(TimeWindow::Year),
+            ..Default::default()
+        };
+        assert_eq!(options.query(""), "after=&limit=100&t=year");
+    }
+
+    #[test]
+    fn range() {
+        let options = ListingOptions {
+            sort: Sort::New,
+            since: Some(Utc.with_ymd_and_hms(2023, 5, 1, 0, 0, 0).unwrap()),
+            until: Some(Utc.with_ymd_and_hms(2023, 5, 7, 0, 0, 0).unwrap()),
+            ..Default::default()
+        };
+
+        // 2023-05-06T19:53:19Z
+        assert!(options.in_range(1683402799.0));
+        assert!(!options.in_range(1683417600.0));
+        assert!(!options.past_range(1683402799.0));
+        // 2023-04-30
+        assert!(!options.in_range(1682812800.0));
+        assert!(options.past_range(1682812800.0));
+    }
+}
@@ -1,7 +1,6 @@
 use console::style;
 use log::{debug, warn};
 use regex::Regex;
-use reqwest::Client;
 
 impl super::Client {
     pub fn logged_in(&self) -> bool {
@@ -10,7 +9,7 @@ impl super::Client {
 
     pub fn bearer_token(&self) -> String {
         if let Some(token) = self.bearer.clone() {
-            return token.clone();
+            return token;
         }
 
         println!("{}", style("You are not logged in").red().bold());
@@ -24,23 +23,22 @@ impl super::Client {
     /// Log into Reddit returning the Bearer
     pub async fn login(&mut self, username: String, password: String) {
         // URL encode the password & username
-        let encoded_password: String;
         let username = urlencoding::encode(&username);
 
         // Reddit is doing a weird thing where * is not urlencoded. Sorry for everyone that has * and %2A in their password
-        if password.contains("*") {
+        let encoded_password = if password.contains('*') {
             debug!("Password has *; URL-encode was rewritten");
-            encoded_password = password.replace("%2A", "*");
+            password.replace("%2A", "*")
         } else {
-            encoded_password = urlencoding::encode(&password).into_owned();
-        }
+            urlencoding::encode(&password).into_owned()
+        };
 
         // Send an HTTP GET request to get the CSRF token
+        let request = self
+            .get(self.reddit("/login/"))
+            .header("User-Agent", &self.user_agent);
         let resp = self
-            .reqwest_client
-            .get("https://www.reddit.com/login/")
-            .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/112.0.5615.121 Safari/537.36")
-            .send()
+            .send(request)
             .await
             .expect("Failed to send HTTP request; to obtain CSRF token");
 
@@ -76,13 +74,13 @@ impl super::Client {
         warn!("CSRF TOKEN: {:}", csrf_token);
 
         // Perform the actual login post request
-        let _x = self.reqwest_client
-        .post("https://www.reddit.com/login")
+        let request = self
+        .post(self.reddit("/login"))
         .header("Content-Type", "application/x-www-form-urlencoded")
         .header("Sec-Ch-Ua", "\"Not:A-Brand\";v=\"99\", \"Chromium\";v=\"112\"")
         .header("Sec-Ch-Ua-Platform", "Windows")
         .header("Sec-Ch-Ua-Mobile", "?0")
-        .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/112.0.5615.121 Safari/537.36")
+        .header("User-Agent", &self.user_agent)
         .header("Origin", "https://www.reddit.com")
         .header("Sec-Fetch-Site", "same-origin")
         .header("Sec-Fetch-Mode", "cors")
@@ -90,15 +88,15 @@ impl super::Client {
         .header("Referrer","https://www.reddit.com/login/")
         .header("Accept-Encoding", "gzip, deflate")
         .header("Accept-Language", "en-GB,en-US;q=0.9,en;q=0.8")
-        .body(form_data)
-        .send()
+        .body(form_data);
+        let _x = self.send(request)
         .await
         .expect("Failed to send HTTP request; to obtain session token");
 
         // Request / to get the bearer token
-        let response = self.reqwest_client
-        .get("https://www.reddit.com/")
-        .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/112.0.5615.121 Safari/537.36")
+        let request = self
+        .get(self.reddit("/"))
+        .header("User-Agent", &self.user_agent)
         .header("Accept-Encoding", "gzip, deflate")
         .header("Accept-Language", "en-GB,en-US;q=0.9,en;q=0.8")
         .header("Referrer","https://www.reddit.com/login/")
@@ -106,8 +104,8 @@ impl super::Client {
         .header("Sec-Fetch-Mode", "navigate")
         .header("Sec-Fetch-Site", "same-origin")
         .header("Sec-Fetch-User", "?1")
-        .header("Te", "trailers")
-        .send()
+        .header("Te", "trailers");
+        let response = self.send(request)
         .await
         .expect("Error getting bearer token");
 
@@ -116,8 +114,8 @@ impl super::Client {
 
         let mut bearer_token: String = String::default();
         for i in bearer_regex.captures_iter(&response.text().await.unwrap()) {
-            for i in i.get(1).iter() {
-                bearer_token = String::from(i.as_str().clone());
+            if let Some(i) = i.get(1) {
+                bearer_token = String::from(i.as_str());
             }
         }
 
@@ -128,10 +126,10 @@ impl super::Client {
 
         debug!("Matrix request body: {:?}", data);
 
-        let response = self.reqwest_client
-        .post("https://matrix.redditspace.com/_matrix/client/r0/login")
+        let request = self
+        .post(self.matrix("/_matrix/client/r0/login"))
         .header("Content-Type", "application/json")
-        .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/112.0.5615.121 Safari/537.36")
+        .header("User-Agent", &self.user_agent)
         .header("Accept", "application/json")
         .header("Origin", "https://chat.reddit.com")
         .header("Sec-Fetch-Site", "cross-site")
@@ -140,8 +138,8 @@ impl super::Client {
         .header("Accept-Encoding", "gzip, deflate")
         .header("Accept-Language", "en-US,en;q=0.5")
         .header("Te", "trailers")
-        .body(data)
-        .send()
+        .body(data);
+        let response = self.send(request)
         .await
         .expect("Failed to send HTTP request; to login to matrix");
 
@@ -1,8 +1,10 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use super::{images, Client};
+use super::{images, Client, Fetched};
+use crate::media_store;
+use crate::progress;
 use chrono::{TimeZone, Utc};
-use log::debug;
+use log::{debug, warn};
 use serde::{Deserialize, Serialize};
 
 /// Struct for a singular message.
@@ -16,6 +18,50 @@ pub struct Message {
 #[derive(Debug, Clone, Serialize)]
 pub enum Content {
     Message(String),
+    Media(Media),
+}
+
+/// A file (image, video, audio or other) sent in a chat
+#[derive(Debug, Clone, Serialize)]
+pub struct Media {
+    /// The original filename
+    pub filename: Option<String>,
+    pub mimetype: Option<String>,
+    /// Size in bytes
+    pub size: Option<u64>,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    pub mxc: String,
+    /// The downloaded file, relative to the exports; None if it was not downloaded
+    pub path: Option<String>,
+    /// Thumbnail of the downloaded file (with --thumbnails), relative to the exports
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+}
+
+/// Which messages to export; empty filters keep every message
+#[derive(Debug, Clone, Default)]
+pub struct MessageFilter {
+    /// Only messages sent at or after this time
+    pub since: Option<chrono::DateTime<Utc>>,
+    /// Only messages sent before this time
+    pub until: Option<chrono::DateTime<Utc>>,
+    /// Only messages of these users (usernames)
+    pub from_users: Vec<String>,
+}
+
+impl MessageFilter {
+    fn in_range(&self, timestamp: chrono::DateTime<Utc>) -> bool {
+        self.since.is_none_or(|since| timestamp >= since) && self.until.is_none_or(|until| timestamp < until)
+    }
+
+    fn from(&self, author: &str) -> bool {
+        self.from_users.is_empty()
+            || self
+                .from_users
+                .iter()
+                .any(|user| user.trim_start_matches("u/").eq_ignore_ascii_case(author))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,14 +85,20 @@ struct InternalMessage {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct InternalContent {
+    msgtype: Option<String>,
+    /// The text of the message, or the original filename of media
     body: Option<String>,
     url: Option<String>,
-    info: Option<InternalImageInfo>,
+    info: Option<InternalMediaInfo>,
 }
 
+/// Info of m.image, m.video, m.audio and m.file messages
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct InternalImageInfo {
+struct InternalMediaInfo {
     mimetype: Option<String>,
+    size: Option<u64>,
+    w: Option<u64>,
+    h: Option<u64>,
 }
 
 pub async fn list_messages(
@@ -56,83 +108,144 @@ pub async fn list_messages(
     no_usernames: bool,
     out: PathBuf,
     redact: bool,
-) -> Vec<Message> {
+    filter: &MessageFilter,
+) -> Fetched<Message> {
     let mut output: Vec<Message> = vec![];
     let mut batch: String = String::new();
+    let mut error = None;
     // Loop over the batching
     loop {
-        let url = format!(
-            "https://matrix.redditspace.com/_matrix/client/r0/rooms/{id}/messages?limit=10000&dir=b&from={batch}");
+        let url = client.matrix(&format!(
+            "/_matrix/client/r0/rooms/{id}/messages?limit=10000&dir=b&from={batch}"));
 
         // Send request to get messages
-        let response = client
-            .reqwest_client
+        let request = client
             .get(url)
-            .header("Authorization", format!("Bearer {}", client.bearer_token()))
-            .send()
-            .await
-            .expect("Failed to send HTTP request; to obtain messages");
-
-        // Deserialize response
-        let messages: Result<InternalMessages, serde_json::Error> =
-            serde_json::from_str(response.text().await.unwrap().as_str());
-        if messages.is_err() {
-            continue; // steamroll
-        }
-        let messages = messages.unwrap();
+            .header("Authorization", format!("Bearer {}", client.bearer_token()));
+        let messages = match get_messages(client, request).await {
+            Ok(messages) => messages,
+            Err(message) => {
+                warn!("Stopping the room after {} message(s): {message}", output.len());
+                error = Some(message);
+                break;
+            }
+        };
         output.reserve(messages.chunk.len());
+        let fetched = output.len();
+
+        // The messages come newest first, so everything after this page is older still
+        let reached_since = messages
+            .chunk
+            .last()
+            .zip(filter.since)
+            .is_some_and(|(oldest, since)| unix_millis_to_utc(oldest.timestamp) < since);
 
         // Iterate over messages
         for message in messages.chunk {
-            // Detect if message is text or file
+            // Skip messages outside of the date range before resolving or downloading anything
+            if !filter.in_range(unix_millis_to_utc(message.timestamp)) {
+                continue;
+            }
 
-            // Handle the no-usernames CLI flag
-            let author: String;
+            // Detect if message is text or file
 
-            if no_usernames {
-                author = "N/A".to_owned();
+            // The name is only looked up if it is exported or needed to filter by author
+            let name = if no_usernames && filter.from_users.is_empty() {
+                None
             } else {
-                author = super::get_user(client, message.sender, redact).await.displayname;
+                Some(super::get_user(client, message.sender, redact).await.displayname)
+            };
+
+            if name.as_deref().is_some_and(|name| !filter.from(name)) {
+                continue;
             }
 
-            if message.content.url.is_some() {
-                // Is a file
-                output.push(Message {
-                    author: author,
-                    timestamp: unix_millis_to_utc(message.timestamp),
-                    content: Content::Message(message.content.url.clone().unwrap()),
-                });
+            // Handle the no-usernames CLI flag
+            let author = match name {
+                Some(name) if !no_usernames => name,
+                _ => super::NO_USERNAME.to_owned(),
+            };
+
+            if let Some(url) = message.content.url {
+                // Is a file (m.image, m.video, m.audio or m.file)
+                debug!("Found {} message", message.content.msgtype.as_deref().unwrap_or("media"));
+
+                let info = message.content.info;
+                let mut media = Media {
+                    filename: message.content.body,
+                    mimetype: info.as_ref().and_then(|info| info.mimetype.clone()),
+                    size: info.as_ref().and_then(|info| info.size),
+                    width: info.as_ref().and_then(|info| info.w),
+                    height: info.as_ref().and_then(|info| info.h),
+                    mxc: url,
+                    path: None,
+                    thumbnail: None,
+                };
+
                 // If option is set download the image
                 if image_download {
-                    images::get_image(
-                        &client,
-                        message.content.url.unwrap(),
+                    let downloaded = images::get_media(
+                        client,
+                        media.mxc.clone(),
+                        media.mimetype.as_deref(),
+                        media.filename.as_deref(),
                         out.clone(),
-                        &std::path::PathBuf::from("./out/messages/images"),
+                        &out.join("messages/images"),
                         redact,
                     )
                     .await;
+
+                    // Link the local file and its thumbnail relative to the exports
+                    if let Some(entry) = downloaded {
+                        media.path = Some(media_store::relative_path(&entry.path, Path::new("messages")));
+                        media.thumbnail = entry
+                            .thumbnail
+                            .map(|thumbnail| media_store::relative_path(&thumbnail, Path::new("messages")));
+                    }
                 }
-            } else if message.content.body.is_some() {
+
+                output.push(Message {
+                    author,
+                    timestamp: unix_millis_to_utc(message.timestamp),
+                    content: Content::Media(media),
+                });
+            } else if let Some(body) = message.content.body {
                 // Text Message
                 output.push(Message {
-                    author: author,
+                    author,
                     timestamp: unix_millis_to_utc(message.timestamp),
-                    content: Content::Message(message.content.body.unwrap()),
+                    content: Content::Message(body),
                 })
             }
         }
 
+        progress::messages(output.len() - fetched);
+
         // Check for end condition
         if messages.end == "t0_0" {
             debug!("Found messages end");
             break;
+        } else if reached_since {
+            debug!("Reached messages before --since");
+            break;
         } else {
             // Update new batch variable
             batch = messages.end;
         }
     }
-    return output;
+    Fetched { items: output, error }
+}
+
+/// Sends the request for a batch of messages and deserializes the response
+async fn get_messages(client: &Client, request: reqwest::RequestBuilder) -> Result<InternalMessages, String> {
+    let response = client
+        .send(request)
+        .await
+        .map_err(|error| format!("Failed to send HTTP request; to obtain messages: {error}"))?;
+    let status = response.status();
+    let text = response.text().await.map_err(|error| format!("Failed to read messages: {error}"))?;
+
+    serde_json::from_str(&text).map_err(|error| format!("Failed to parse messages ({status}): {error}"))
 }
 
 fn unix_millis_to_utc(unix_time: i64) -> chrono::DateTime<Utc> {
@@ -154,9 +267,84 @@ mod tests {
 
         client.login(username, password).await;
 
-        let rooms = super::super::download_rooms(&client, true, false, PathBuf::from("./out"), false);
+        let rooms = super::super::list_rooms(&client, false).await.unwrap();
+
+        let _messages = super::list_messages(&client, rooms[1].clone().id, true, false, PathBuf::from("./out"), false, &Default::default()).await;
+    }
+
+    #[tokio::test]
+    async fn list_messages_offline() {
+        let (client, _server) = super::super::mock::mock_client().await;
+
+        let messages = super::list_messages(&client, "!mockroom0001:reddit.com".to_owned(), false, false, PathBuf::from("./out"), false, &Default::default()).await.items;
+
+        // The membership event has neither a body nor a file and is dropped
+        assert_eq!(messages.len(), 3);
+
+        let super::Content::Media(video) = &messages[0].content else { panic!("Expected media") };
+        assert_eq!(video.mxc, "mxc://reddit.com/mockvideo0001");
+        assert_eq!(video.filename.as_deref(), Some("clip.mp4"));
+        assert_eq!(video.mimetype.as_deref(), Some("video/mp4"));
+        assert_eq!((video.width, video.height), (Some(1280), Some(720)));
+        assert_eq!(video.path, None);
+
+        let super::Content::Media(image) = &messages[1].content else { panic!("Expected media") };
+        assert_eq!(image.mxc, "mxc://reddit.com/mockimage0001");
+        assert_eq!(image.size, Some(67));
+
+        let super::Content::Message(text) = &messages[2].content else { panic!("Expected text") };
+        assert_eq!(text, "Testing");
+        assert_eq!(messages[2].author, "rexitTest");
+        assert_eq!(messages[2].timestamp.timestamp(), 1683402799);
+    }
+
+    #[tokio::test]
+    async fn list_messages_downloads_into_out() {
+        let (client, _server) = super::super::mock::mock_client().await;
+        let out = tempfile::tempdir().unwrap();
+
+        let messages = super::list_messages(&client, "!mockroom0001:reddit.com".to_owned(), true, true, out.path().to_path_buf(), false, &Default::default()).await.items;
+
+        // The media ends up below the out folder and the messages link to it
+        let super::Content::Media(image) = &messages[1].content else { panic!("Expected media") };
+        let path = image.path.as_deref().unwrap();
+        assert_eq!(path, "images/mockimage0001.png");
+        assert_eq!(image.thumbnail, None);
+        assert!(out.path().join("messages").join(path).exists());
+
+        let super::Content::Media(video) = &messages[0].content else { panic!("Expected media") };
+        assert_eq!(video.path.as_deref(), Some("images/mockvideo0001.mp4"));
+    }
+
+    #[tokio::test]
+    async fn list_messages_filtered() {
+        let (client, _server) = super::super::mock::mock_client().await;
+        let id = "!mockroom0001:reddit.com".to_owned();
+
+        // Only the video and the image are sent after the text
+        let filter = super::MessageFilter {
+            since: chrono::DateTime::from_timestamp(1683402800, 0),
+            ..Default::default()
+        };
+        let messages = super::list_messages(&client, id.clone(), false, false, PathBuf::from("./out"), false, &filter).await.items;
+        assert_eq!(messages.len(), 2);
+
+        let filter = super::MessageFilter {
+            until: chrono::DateTime::from_timestamp(1683402800, 0),
+            from_users: vec!["u/RexitTest".to_owned()],
+            ..Default::default()
+        };
+        // Authors are resolved for the filter but not exported without usernames
+        let messages = super::list_messages(&client, id.clone(), false, true, PathBuf::from("./out"), false, &filter).await.items;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].author, "N/A");
 
-        let _messages = super::list_messages(&client, rooms.await[1].clone().id, true, false, PathBuf::from("./out"), false).await;
+        let filter = super::MessageFilter {
+            from_users: vec!["someoneElse".to_owned()],
+            ..Default::default()
+        };
+        let messages = super::list_messages(&client, id, false, false, PathBuf::from("./out"), false, &filter).await.items;
+        assert!(messages.is_empty());
     }
 
     fn get_login() -> (String, String) {
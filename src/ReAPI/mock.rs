@@ -0,0 +1,200 @@
+//! Local stand-in for Reddit and the Matrix homeserver used by the offline tests.
+//!
+//! The responses are recorded fixtures from `test_resources/test_cases/ReAPI/mock_server`.
+use std::path::PathBuf;
+
+use wiremock::matchers::{header, method, path, path_regex, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use super::{new_client, Client};
+
+/// The bearer token the mock server expects
+pub const TOKEN: &str = "mock_token";
+
+/// Reads a recorded response from the fixtures folder
+pub fn fixture(name: &str) -> serde_json::Value {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("test_resources/test_cases/ReAPI/mock_server")
+        .join(name)
+        .with_extension("json");
+
+    let contents = std::fs::read_to_string(path).expect("Error reading fixture");
+
+    serde_json::from_str(&contents).expect("Error parsing fixture")
+}
+
+/// Path of a recorded media file
+pub fn media(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("test_resources/test_cases/ReAPI/mock_server")
+        .join(name)
+}
+
+/// Starts the mock server and returns a logged in client pointed at it.
+///
+/// The server shuts down when dropped, so keep it alive for the duration of the test.
+pub async fn mock_client() -> (Client, MockServer) {
+    let server = MockServer::start().await;
+
+    // Matrix
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/joined_rooms"))
+        .and(header("Authorization", format!("Bearer {TOKEN}").as_str()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("joined_rooms")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/[^/]+/messages$"))
+        .and(header("Authorization", format!("Bearer {TOKEN}").as_str()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("messages")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/[^/]+/state/m.room.name$"))
+        .and(header("Authorization", format!("Bearer {TOKEN}").as_str()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("room_name")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/[^/]+/joined_members$"))
+        .and(header("Authorization", format!("Bearer {TOKEN}").as_str()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("joined_members")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/account/whoami"))
+        .and(header("Authorization", format!("Bearer {TOKEN}").as_str()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("whoami")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/profile/[^/]+/displayname$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("displayname")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/r0/download/reddit.com/mockimage0001"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(std::fs::read(media("image.png")).unwrap(), "image/png"),
+        )
+        .mount(&server)
+        .await;
+
+    // Reddit serves animated gallery images as MP4 with a .gif name; the type is only in the query
+    Mock::given(method("GET"))
+        .and(path("/b2.gif"))
+        .and(query_param("format", "mp4"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(b"\0\0\0\x18ftypmp42".to_vec(), "application/octet-stream"))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/r0/download/reddit.com/mockvideo0001"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(b"not really a video".to_vec(), "application/octet-stream"))
+        .mount(&server)
+        .await;
+
+    // Reddit
+    Mock::given(method("GET"))
+        .and(path("/saved.json"))
+        .and(query_param("after", ""))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("saved")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/r/rexitTest/hot.json"))
+        .and(query_param("after", ""))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("subreddit_page1")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/r/rexitTest/hot.json"))
+        .and(query_param("after", "t3_13a9rn6"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("subreddit_page2")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/user/rexitTest/comments.json"))
+        .and(query_param("after", ""))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("user_comments")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/user/rexitTest/upvoted.json"))
+        .and(query_param("after", ""))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("upvoted")))
+        .mount(&server)
+        .await;
+
+    for (message_box, name) in [("inbox", "inbox"), ("sent", "sent"), ("unread", "inbox")] {
+        Mock::given(method("GET"))
+            .and(path(format!("/message/{message_box}.json")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(fixture(name)))
+            .mount(&server)
+            .await;
+    }
+
+    for (route, name) in [
+        ("/r/rexitTest/about.json", "about"),
+        ("/r/rexitTest/about/rules.json", "rules"),
+        ("/r/rexitTest/wiki/pages.json", "wiki_pages"),
+        ("/r/rexitTest/wiki/index.json", "wiki_index"),
+    ] {
+        Mock::given(method("GET"))
+            .and(path(route))
+            .respond_with(ResponseTemplate::new(200).set_body_json(fixture(name)))
+            .mount(&server)
+            .await;
+    }
+
+    Mock::given(method("GET"))
+        .and(path("/r/rexitTest/wiki/config/automoderator.json"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(serde_json::json!({ "reason": "PAGE_PRIVATE" })))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/user/rexitTest/m/mock/hot.json"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("subreddit_page2")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/r/rexitTest/search.json"))
+        .and(query_param("q", "Testing Rexit"))
+        .and(query_param("restrict_sr", "1"))
+        .and(query_param("sort", "relevance"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("subreddit_page2")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/r/rexitTest/comments/13a9rn6/testing_rexit.json"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("comments")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/morechildren.json"))
+        .and(query_param("link_id", "t3_13a9rn6"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("morechildren")))
+        .mount(&server)
+        .await;
+
+    let mut client = new_client(false);
+    client.set_matrix_url(&server.uri());
+    client.set_reddit_url(&server.uri());
+    client.login_with_token(TOKEN.to_owned());
+
+    (client, server)
+}
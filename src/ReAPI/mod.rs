@@ -1,58 +1,261 @@
 //! Reddit matrix api
 #![allow(non_snake_case, dead_code)]
 
+mod comments;
 mod images;
+mod inbox;
+mod listing;
 mod login;
-mod messages;
+pub(crate) mod messages;
+mod post_media;
+mod posts;
+mod profile;
+mod record;
 mod rooms;
 pub(crate) mod saved_posts;
+mod search;
 pub(crate) mod subreddit;
+mod subreddit_meta;
+mod transport;
 mod users;
 
-pub use images::Image;
+#[cfg(test)]
+pub(crate) mod mock;
 
-pub use rooms::download_rooms;
+pub use comments::Comment;
+pub use comments::CommentOptions;
+
+pub use images::repair_media;
+
+pub use inbox::download_inbox;
+
+pub use messages::MessageFilter;
+pub use rooms::{download_rooms, list_rooms, RoomFilter, RoomInfo};
 pub use rooms::Room;
 
 pub use saved_posts::download_saved_posts;
-pub use saved_posts::SavedPost;
+pub use saved_posts::SavedItem;
+
+pub use subreddit::{download_subreddit, expand_names, folder_name, is_multireddit};
+pub use subreddit_meta::{download_meta, meta_requests, SubredditMeta};
 
-pub use subreddit::download_subreddit;
-pub use subreddit::Post;
+pub use posts::Post;
+pub use posts::PostOptions;
+
+pub use listing::{ListingOptions, SearchSort, Sort, TimeWindow};
+
+pub use search::download_search;
+
+pub use profile::{download_user_listing, user_name, UserListing};
 
 pub use messages::Content;
 pub use messages::Message;
 
+pub use transport::Transport;
+
+
 pub use users::get_user;
-pub use users::User;
+pub use users::whoami;
+
+use console::style;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The default Matrix homeserver of Reddit chat
+pub const MATRIX_URL: &str = "https://matrix.redditspace.com";
+/// The default Reddit API
+pub const REDDIT_URL: &str = "https://www.reddit.com";
+/// The default user agent, a desktop browser since the login only works for those
+pub const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/112.0.5615.121 Safari/537.36";
+
+/// Stands in for usernames with `--no-usernames`
+pub(crate) const NO_USERNAME: &str = "N/A";
+
+/// Author of posts and comments whose account was deleted
+pub(crate) fn deleted() -> String {
+    "[deleted]".to_owned()
+}
+
+/// What a paged download fetched; after an error only the pages before it
+#[derive(Debug, Clone)]
+pub struct Fetched<T> {
+    pub items: Vec<T>,
+    pub error: Option<String>,
+}
+
+impl<T> Fetched<T> {
+    pub(crate) fn map<U>(self, f: impl FnMut(T) -> Option<U>) -> Fetched<U> {
+        Fetched {
+            items: self.items.into_iter().filter_map(f).collect(),
+            error: self.error,
+        }
+    }
+}
 
 pub struct Client {
     reqwest_client: reqwest::Client,
+    transport: Box<dyn Transport>,
+    matrix_url: String,
+    reddit_url: String,
+    user_agent: String,
     bearer: Option<String>,
 }
 
+impl Client {
+    /// Replaces the transport requests are sent through
+    pub fn set_transport(&mut self, transport: impl Transport + 'static) {
+        self.transport = Box::new(transport);
+    }
+
+    /// Sets the base URL of the Matrix homeserver (Default: [MATRIX_URL])
+    pub fn set_matrix_url(&mut self, url: &str) {
+        self.matrix_url = url.trim_end_matches('/').to_owned();
+    }
+
+    /// Sets the base URL of the Reddit API (Default: [REDDIT_URL])
+    pub fn set_reddit_url(&mut self, url: &str) {
+        self.reddit_url = url.trim_end_matches('/').to_owned();
+    }
+
+    /// Builds a URL on the Matrix homeserver from an absolute path
+    pub(crate) fn matrix(&self, path: &str) -> String {
+        format!("{}{}", self.matrix_url, path)
+    }
+
+    /// Builds a URL on the Reddit API from an absolute path
+    pub(crate) fn reddit(&self, path: &str) -> String {
+        format!("{}{}", self.reddit_url, path)
+    }
+
+    pub(crate) fn get(&self, url: impl reqwest::IntoUrl) -> reqwest::RequestBuilder {
+        self.reqwest_client.get(url)
+    }
+
+    pub(crate) fn post(&self, url: impl reqwest::IntoUrl) -> reqwest::RequestBuilder {
+        self.reqwest_client.post(url)
+    }
+
+    /// Sends a request through the configured transport
+    pub(crate) async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> reqwest::Result<reqwest::Response> {
+        self.transport.execute(request.build()?).await
+    }
+}
+
+/// Network settings of the [Client]
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// Trust any certificate (used in development to trust a proxy)
+    pub accept_invalid_certs: bool,
+    /// HTTP, HTTPS or SOCKS5 proxy all requests go through (Example: socks5://127.0.0.1:9050)
+    pub proxy: Option<String>,
+    /// PEM file with additional root certificates to trust
+    pub ca_cert: Option<PathBuf>,
+    pub user_agent: String,
+    pub timeout: Duration,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            accept_invalid_certs: false,
+            proxy: None,
+            ca_cert: None,
+            user_agent: USER_AGENT.to_owned(),
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
 pub fn new_client(debug: bool) -> Client {
+    new_client_with(ClientOptions {
+        accept_invalid_certs: debug,
+        ..Default::default()
+    })
+}
+
+pub fn new_client_with(options: ClientOptions) -> Client {
     // Build the client
-    let client: reqwest::Client;
-    if debug {
-        client = reqwest::Client::builder()
-            .cookie_store(true)
-            .timeout(std::time::Duration::from_secs(60))
-            .danger_accept_invalid_certs(true) // Used in development to trust a proxy
-            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/112.0.5615.121 Safari/537.36")
-            .build()
-            .expect("Error making Reqwest Client");
-    } else {
-        client = reqwest::Client::builder()
-            .cookie_store(true)
-            .timeout(std::time::Duration::from_secs(60))
-            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/112.0.5615.121 Safari/537.36")
-            .build()
-            .expect("Error making Reqwest Client");
+    let mut builder = reqwest::Client::builder()
+        .cookie_store(true)
+        .timeout(options.timeout)
+        .danger_accept_invalid_certs(options.accept_invalid_certs)
+        .user_agent(options.user_agent.clone());
+
+    if let Some(proxy) = options.proxy {
+        let Ok(proxy) = reqwest::Proxy::all(proxy.as_str()) else {
+            println!("{}", style(format!("Invalid proxy URL: {proxy}")).red().bold());
+            crate::exit!(1, "Invalid proxy URL");
+        };
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(path) = options.ca_cert {
+        let Ok(pem) = std::fs::read(&path) else {
+            println!("{}", style(format!("Could not read CA certificate: {}", path.display())).red().bold());
+            crate::exit!(1, "Could not read CA certificate");
+        };
+        let Ok(certificates) = reqwest::Certificate::from_pem_bundle(&pem) else {
+            println!("{}", style(format!("Invalid PEM in CA certificate: {}", path.display())).red().bold());
+            crate::exit!(1, "Invalid PEM in CA certificate");
+        };
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
     }
 
+    let client = builder.build().expect("Error making Reqwest Client");
+
     Client {
-        reqwest_client: client,
+        reqwest_client: client.clone(),
+        transport: Box::new(client),
+        matrix_url: MATRIX_URL.to_owned(),
+        reddit_url: REDDIT_URL.to_owned(),
+        user_agent: options.user_agent,
         bearer: None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use wiremock::matchers::{header, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn user_agent() {
+        let server = MockServer::start().await;
+        Mock::given(path("/"))
+            .and(header("User-Agent", "rexit-test"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let client = super::new_client_with(super::ClientOptions {
+            user_agent: "rexit-test".to_owned(),
+            ..Default::default()
+        });
+
+        let response = client.send(client.get(server.uri())).await.unwrap();
+        assert!(response.status().is_success());
+    }
+
+    #[tokio::test]
+    async fn proxy() {
+        // The mock server acts as the proxy, so a request to an unresolvable host still reaches it
+        let server = MockServer::start().await;
+        Mock::given(path("/saved.json"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let client = super::new_client_with(super::ClientOptions {
+            proxy: Some(server.uri()),
+            ..Default::default()
+        });
+
+        let response = client.send(client.get("http://reddit.invalid/saved.json")).await.unwrap();
+        assert!(response.status().is_success());
+    }
+}
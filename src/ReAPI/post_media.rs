@@ -0,0 +1,378 @@
+//! Finds and downloads the media of a Reddit post: galleries, Reddit hosted videos, direct image links and previews.
+use std::path::{Path, PathBuf};
+
+use log::{debug, info, warn};
+use serde_json::Value;
+use url::Url;
+
+use super::{images, Client};
+use crate::media_store::{self, MediaEntry};
+use crate::progress;
+
+/// Extensions of links that point directly to a file
+const DIRECT_EXTENSIONS: [&str; 8] = ["jpg", "jpeg", "png", "gif", "webp", "mp4", "webm", "gifv"];
+
+/// Hosts that only serve files
+const DIRECT_HOSTS: [&str; 2] = ["i.redd.it", "i.imgur.com"];
+
+/// Names of the separate DASH audio track of v.redd.it videos (newer and older uploads)
+const DASH_AUDIO: [&str; 2] = ["DASH_AUDIO_128.mp4", "DASH_audio.mp4"];
+
+/// A media item of a post
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostMedia {
+    /// Any file that can be downloaded as is
+    File(String),
+    /// A v.redd.it video, whose audio Reddit serves as a separate track
+    Video { url: String, audio: Vec<String> },
+}
+
+impl PostMedia {
+    pub fn url(&self) -> &str {
+        match self {
+            PostMedia::File(url) => url,
+            PostMedia::Video { url, .. } => url,
+        }
+    }
+}
+
+/// Resolves the originals of all media in a post (the `data` of a listing child).
+///
+/// Previews are only used if the post has nothing better.
+pub fn resolve(post: &Value) -> Vec<PostMedia> {
+    let mut media = gallery(post);
+
+    // Direct links (Example: a .gif on i.redd.it) are the originals the video preview was made from
+    let link = post["url_overridden_by_dest"].as_str().or(post["url"].as_str());
+    match link.filter(|_| media.is_empty()).and_then(direct_link) {
+        Some(url) => media.push(PostMedia::File(url)),
+        None => media.extend(reddit_video(post)),
+    }
+
+    // Crossposts carry the media of the original post
+    if media.is_empty() {
+        if let Some(parent) = post["crosspost_parent_list"].as_array().and_then(|list| list.first()) {
+            media = resolve(parent);
+        }
+    }
+
+    if media.is_empty() {
+        media = previews(post);
+    }
+
+    media
+}
+
+/// Images and animations of a gallery, in gallery order
+fn gallery(post: &Value) -> Vec<PostMedia> {
+    let Some(metadata) = post["media_metadata"].as_object() else {
+        return vec![];
+    };
+
+    // gallery_data has the order, media_metadata (also used for images in text posts) only the items
+    let ids: Vec<String> = match post["gallery_data"]["items"].as_array() {
+        Some(items) => items
+            .iter()
+            .filter_map(|item| item["media_id"].as_str())
+            .map(|id| id.to_owned())
+            .collect(),
+        None => metadata.keys().cloned().collect(),
+    };
+
+    let mut media = vec![];
+    for id in ids {
+        // Removed or failed items can be listed in the gallery without metadata
+        let Some(item) = metadata.get(&id) else {
+            warn!("Skipping gallery item {id} without metadata");
+            continue;
+        };
+        if item["status"].as_str() != Some("valid") {
+            warn!("Skipping gallery item {id} with status {}", item["status"]);
+            continue;
+        }
+
+        match item["e"].as_str() {
+            Some("Image") => {
+                // "image/jpg" -> https://i.redd.it/<id>.jpg is the original
+                let extension = item["m"]
+                    .as_str()
+                    .and_then(|mimetype| mimetype.split('/').nth(1))
+                    .unwrap_or("jpg");
+                media.push(PostMedia::File(format!("https://i.redd.it/{id}.{extension}")));
+            }
+            Some("AnimatedImage") => {
+                let url = item["s"]["mp4"].as_str().or(item["s"]["gif"].as_str());
+                if let Some(url) = url {
+                    media.push(PostMedia::File(decode(url)));
+                }
+            }
+            kind => debug!("Skipping gallery item {id} of kind {kind:?}"),
+        }
+    }
+
+    media
+}
+
+/// The video of a post hosted on v.redd.it (or a GIF converted to one)
+fn reddit_video(post: &Value) -> Option<PostMedia> {
+    let video = [
+        &post["secure_media"]["reddit_video"],
+        &post["media"]["reddit_video"],
+        &post["preview"]["reddit_video_preview"],
+    ]
+    .into_iter()
+    .find(|video| video.is_object())?;
+
+    // The fallback is the highest quality stream without audio, e.g. https://v.redd.it/<id>/DASH_720.mp4?source=fallback
+    let mut url = Url::parse(video["fallback_url"].as_str()?).ok()?;
+    url.set_query(None);
+
+    let audio = if video["is_gif"].as_bool() == Some(true) {
+        vec![]
+    } else {
+        DASH_AUDIO
+            .iter()
+            .filter_map(|name| url.join(name).ok())
+            .map(|url| url.to_string())
+            .collect()
+    };
+
+    Some(PostMedia::Video {
+        url: url.to_string(),
+        audio,
+    })
+}
+
+/// Returns the URL to download if the link points directly to a file
+fn direct_link(url: &str) -> Option<String> {
+    let parsed = Url::parse(url).ok()?;
+    let host = parsed.host_str()?;
+    let extension = Path::new(parsed.path())
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+
+    match extension.as_deref() {
+        // Imgur serves .gifv as a HTML page around the .mp4
+        Some("gifv") => Some(url.replace(".gifv", ".mp4")),
+        Some(extension) if DIRECT_EXTENSIONS.contains(&extension) => Some(url.to_owned()),
+        _ if DIRECT_HOSTS.contains(&host) => Some(url.to_owned()),
+        _ => None,
+    }
+}
+
+/// The (low resolution) preview images of a post
+fn previews(post: &Value) -> Vec<PostMedia> {
+    post["preview"]["images"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|image| image["source"]["url"].as_str())
+        .map(|url| PostMedia::File(decode(url)))
+        .collect()
+}
+
+/// The URLs in the post JSON are HTML encoded (&amp; etc)
+fn decode(url: &str) -> String {
+    html_escape::decode_html_entities(url).to_string()
+}
+
+/// Downloads all media of a post into `path`.
+///
+/// With `mux_audio` Reddit videos are merged with their audio track using ffmpeg, otherwise they are saved without sound.
+pub async fn download(client: &Client, media: &[PostMedia], out: &Path, path: &Path, mux_audio: bool, redact: bool) {
+    for item in media {
+        match item {
+            PostMedia::Video { url, audio } if mux_audio => {
+                download_video(client, url, audio, out, path, redact).await;
+            }
+            _ => {
+                images::get_image(client, item.url().to_owned(), out.to_path_buf(), path, redact).await;
+            }
+        }
+    }
+}
+
+async fn download_video(
+    client: &Client,
+    url: &str,
+    audio: &[String],
+    out: &Path,
+    path: &Path,
+    redact: bool,
+) -> Option<MediaEntry> {
+    if let Some(entry) = media_store::lookup(out, url) {
+        info!("Image was already downloaded; Skipping");
+        progress::media_skipped();
+        return Some(entry);
+    }
+
+    // Handle redaction
+    if redact {
+        info!(target: "get_image", "Getting video: [REDACTED]");
+    } else {
+        info!(target: "get_image", "Getting video: {url}");
+    }
+
+    let Some((_, video)) = images::fetch(client, url.to_owned()).await else {
+        progress::media_failed();
+        return None;
+    };
+    progress::media_downloaded(video.len());
+    // e.g. https://v.redd.it/<id>/DASH_720.mp4 -> <id>.mp4
+    let id = Url::parse(url).ok()?.path_segments()?.next()?.to_owned();
+    let file = path.join(id).with_extension("mp4");
+
+    let mut track = None;
+    for candidate in audio {
+        if let Some((_, data)) = images::fetch(client, candidate.clone()).await {
+            track = Some(data);
+            break;
+        }
+    }
+
+    let data = match track {
+        Some(track) => mux(&video, &track).await.unwrap_or(video),
+        // Videos without sound have no audio track
+        None => video,
+    };
+
+    Some(media_store::store(out, url, &data, &file))
+}
+
+/// Merges a video and an audio track without re-encoding
+async fn mux(video: &[u8], audio: &[u8]) -> Option<Vec<u8>> {
+    let dir = tempfile::tempdir().ok()?;
+    let input: PathBuf = dir.path().join("video.mp4");
+    let track: PathBuf = dir.path().join("audio.mp4");
+    let output: PathBuf = dir.path().join("muxed.mp4");
+    std::fs::write(&input, video).ok()?;
+    std::fs::write(&track, audio).ok()?;
+
+    let status = tokio::process::Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-i"])
+        .arg(&input)
+        .arg("-i")
+        .arg(&track)
+        .args(["-c", "copy"])
+        .arg(&output)
+        .status()
+        .await;
+
+    match status {
+        Ok(status) if status.success() => std::fs::read(output).ok(),
+        Ok(status) => {
+            warn!("ffmpeg failed ({status}); saving the video without sound");
+            None
+        }
+        Err(error) => {
+            warn!("Could not run ffmpeg ({error}); saving the video without sound");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::PostMedia;
+
+    #[test]
+    fn gallery() {
+        // c3 failed and d4 has no metadata at all (removed items)
+        let post = json!({
+            "gallery_data": { "items": [{ "media_id": "b2" }, { "media_id": "a1" }, { "media_id": "c3" }, { "media_id": "d4" }] },
+            "media_metadata": {
+                "a1": { "status": "valid", "e": "Image", "m": "image/png" },
+                "b2": { "status": "valid", "e": "AnimatedImage", "s": { "mp4": "https://preview.redd.it/b2.gif?format=mp4&amp;s=x" } },
+                "c3": { "status": "failed" }
+            },
+            "preview": { "images": [{ "source": { "url": "https://preview.redd.it/low.jpg" } }] }
+        });
+
+        assert_eq!(
+            super::resolve(&post),
+            vec![
+                PostMedia::File("https://preview.redd.it/b2.gif?format=mp4&s=x".to_owned()),
+                PostMedia::File("https://i.redd.it/a1.png".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reddit_video() {
+        let post = json!({
+            "url": "https://v.redd.it/abc123",
+            "secure_media": { "reddit_video": {
+                "fallback_url": "https://v.redd.it/abc123/DASH_720.mp4?source=fallback",
+                "is_gif": false
+            } }
+        });
+
+        assert_eq!(
+            super::resolve(&post),
+            vec![PostMedia::Video {
+                url: "https://v.redd.it/abc123/DASH_720.mp4".to_owned(),
+                audio: vec![
+                    "https://v.redd.it/abc123/DASH_AUDIO_128.mp4".to_owned(),
+                    "https://v.redd.it/abc123/DASH_audio.mp4".to_owned(),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn direct_links() {
+        let post = json!({ "url": "https://i.imgur.com/xyz.gifv" });
+        assert_eq!(super::resolve(&post), vec![PostMedia::File("https://i.imgur.com/xyz.mp4".to_owned())]);
+
+        let post = json!({ "url_overridden_by_dest": "https://i.redd.it/orig.jpeg", "url": "https://www.reddit.com/x" });
+        assert_eq!(super::resolve(&post), vec![PostMedia::File("https://i.redd.it/orig.jpeg".to_owned())]);
+
+        // Articles fall back to the preview
+        let post = json!({
+            "url": "https://example.com/article",
+            "preview": { "images": [{ "source": { "url": "https://external-preview.redd.it/p.jpg?a=1&amp;b=2" } }] }
+        });
+        assert_eq!(super::resolve(&post), vec![PostMedia::File("https://external-preview.redd.it/p.jpg?a=1&b=2".to_owned())]);
+    }
+
+    #[test]
+    fn direct_gif_over_preview_video() {
+        let post = json!({
+            "url": "https://i.redd.it/animated.gif",
+            "preview": { "reddit_video_preview": {
+                "fallback_url": "https://v.redd.it/transcoded/DASH_480.mp4?source=fallback",
+                "is_gif": true
+            } }
+        });
+        assert_eq!(super::resolve(&post), vec![PostMedia::File("https://i.redd.it/animated.gif".to_owned())]);
+
+        // Links to other pages still get the preview video
+        let post = json!({
+            "url": "https://gfycat.com/somegif",
+            "preview": { "reddit_video_preview": {
+                "fallback_url": "https://v.redd.it/transcoded/DASH_480.mp4?source=fallback",
+                "is_gif": true
+            } }
+        });
+        assert_eq!(
+            super::resolve(&post),
+            vec![PostMedia::Video {
+                url: "https://v.redd.it/transcoded/DASH_480.mp4".to_owned(),
+                audio: vec![],
+            }]
+        );
+    }
+
+    #[test]
+    fn crosspost() {
+        let post = json!({
+            "url": "/r/rexitTest/comments/abc/original/",
+            "crosspost_parent_list": [{ "url": "https://i.redd.it/original.png" }]
+        });
+
+        assert_eq!(super::resolve(&post), vec![PostMedia::File("https://i.redd.it/original.png".to_owned())]);
+    }
+}
@@ -0,0 +1,225 @@
+//! The posts (`t3` things) of listings like saved posts and subreddits.
+use std::path::Path;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::comments::{self, Comment, CommentOptions};
+use super::{post_media, Client};
+
+/// What to download along with each post
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PostOptions {
+    /// Download the media of posts
+    pub images: bool,
+    /// Merge Reddit videos with their audio track (requires ffmpeg)
+    pub mux_audio: bool,
+    /// Download the discussion as well
+    pub comments: Option<CommentOptions>,
+    /// Replace the authors of posts and comments with [super::NO_USERNAME]
+    pub no_usernames: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Post {
+    /// Fullname of the post (Example: t3_13a9rn6)
+    #[serde(default)]
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub author: String,
+    pub subreddit_name: String,
+    pub permalink: String,
+    /// Where link posts point to
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub domain: String,
+    pub img_url: Vec<String>,
+    pub body_text: String,
+    #[serde(default)]
+    pub score: i64,
+    #[serde(default)]
+    pub upvote_ratio: f64,
+    #[serde(default)]
+    pub num_comments: u64,
+    /// Unix timestamp (UTC)
+    #[serde(default)]
+    pub created: f64,
+    #[serde(default)]
+    pub flair: Option<String>,
+    #[serde(default)]
+    pub nsfw: bool,
+    #[serde(default)]
+    pub spoiler: bool,
+    #[serde(default)]
+    pub comments: Vec<Comment>,
+}
+
+/// The `data` of a post as returned by the API
+#[derive(Debug, Deserialize)]
+struct PostData {
+    name: String,
+    title: String,
+    #[serde(default = "super::deleted")]
+    author: String,
+    subreddit_name_prefixed: String,
+    permalink: String,
+    #[serde(default)]
+    is_self: bool,
+    url: Option<String>,
+    #[serde(default)]
+    domain: String,
+    #[serde(default)]
+    selftext: String,
+    #[serde(default)]
+    score: i64,
+    #[serde(default)]
+    upvote_ratio: f64,
+    #[serde(default)]
+    num_comments: u64,
+    #[serde(default)]
+    created_utc: f64,
+    link_flair_text: Option<String>,
+    #[serde(default)]
+    over_18: bool,
+    #[serde(default)]
+    spoiler: bool,
+}
+
+impl Post {
+    /// Builds a post from the `data` of a listing child, without media and comments
+    pub(crate) fn from_data(data: &Value) -> Option<Post> {
+        let data = match PostData::deserialize(data) {
+            Ok(data) => data,
+            Err(error) => {
+                warn!("Failed to parse post: {error}");
+                return None;
+            }
+        };
+
+        Some(Post {
+            id: data.name,
+            title: data.title,
+            author: data.author,
+            subreddit_name: data.subreddit_name_prefixed,
+            permalink: data.permalink,
+            // Text posts link to themselves
+            url: data.url.filter(|_| !data.is_self),
+            domain: data.domain,
+            img_url: vec![],
+            body_text: data.selftext,
+            score: data.score,
+            upvote_ratio: data.upvote_ratio,
+            num_comments: data.num_comments,
+            created: data.created_utc,
+            flair: data.link_flair_text.filter(|flair| !flair.is_empty()),
+            nsfw: data.over_18,
+            spoiler: data.spoiler,
+            comments: vec![],
+        })
+    }
+
+    /// Replaces the authors of the post and its comments with [super::NO_USERNAME]
+    pub(crate) fn anonymize(&mut self) {
+        self.author = super::NO_USERNAME.to_owned();
+        self.comments.iter_mut().for_each(Comment::anonymize);
+    }
+}
+
+/// Builds a post from the `data` of a listing child and downloads its media (into `folder`) and comments
+pub(crate) async fn download_post(
+    client: &Client,
+    data: &Value,
+    options: PostOptions,
+    out: &Path,
+    folder: &Path,
+    redact: bool,
+) -> Option<Post> {
+    // Broken posts are skipped before anything is downloaded for them
+    let mut post = Post::from_data(data)?;
+
+    // Get all media (galleries, videos, direct links or else previews)
+    let media = post_media::resolve(data);
+
+    if options.images {
+        post_media::download(client, &media, out, folder, options.mux_audio, redact).await;
+    }
+
+    post.img_url = media.iter().map(|item| item.url().to_owned()).collect();
+
+    // Get the discussion
+    if let Some(comment_options) = options.comments {
+        post.comments = comments::download_comments(client, &post.permalink, comment_options).await;
+    }
+
+    if options.no_usernames {
+        post.anonymize();
+    }
+
+    Some(post)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    #[test]
+    fn from_data() {
+        let data = json!({
+            "name": "t3_abc",
+            "title": "A link",
+            "author": "rexitTest",
+            "subreddit_name_prefixed": "r/rexitTest",
+            "permalink": "/r/rexitTest/comments/abc/a_link/",
+            "is_self": false,
+            "url": "https://example.com/article",
+            "domain": "example.com",
+            "selftext": "",
+            "score": 42,
+            "upvote_ratio": 0.97,
+            "num_comments": 7,
+            "created_utc": 1683402799.0,
+            "link_flair_text": "News",
+            "over_18": true,
+            "spoiler": false
+        });
+
+        let post = super::Post::from_data(&data).unwrap();
+
+        assert_eq!(post.id, "t3_abc");
+        assert_eq!(post.author, "rexitTest");
+        assert_eq!(post.url.as_deref(), Some("https://example.com/article"));
+        assert_eq!(post.score, 42);
+        assert_eq!(post.num_comments, 7);
+        assert_eq!(post.flair.as_deref(), Some("News"));
+        assert!(post.nsfw);
+
+        // Missing fields of deleted posts fall back to defaults, broken ones are skipped
+        let post = super::Post::from_data(
+            &json!({ "name": "t3_x", "title": "x", "subreddit_name_prefixed": "r/x", "permalink": "/x", "is_self": true, "url": "/x" }),
+        )
+        .unwrap();
+        assert_eq!(post.author, "[deleted]");
+        assert_eq!(post.url, None);
+        assert!(super::Post::from_data(&json!({ "title": "x" })).is_none());
+    }
+
+    #[tokio::test]
+    async fn broken_post_downloads_nothing() {
+        let (client, server) = super::super::mock::mock_client().await;
+        let out = tempfile::tempdir().unwrap();
+        let out = out.path();
+
+        let data = json!({ "title": "x", "url": format!("{}/b2.gif?format=mp4", server.uri()) });
+        let options = super::PostOptions {
+            images: true,
+            ..Default::default()
+        };
+
+        assert!(super::download_post(&client, &data, options, out, out, false).await.is_none());
+        assert!(crate::media_store::entries(out).is_empty());
+        assert!(server.received_requests().await.unwrap_or_default().is_empty());
+    }
+}
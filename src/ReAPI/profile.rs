@@ -0,0 +1,204 @@
+//! Downloads the history of a user profile: submitted posts, comments and (for the logged in user) votes, hidden and gilded items.
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use log::info;
+
+use super::listing::{self, ListingOptions, ListingSort, Sort};
+use super::posts::PostOptions;
+use super::{Client, Fetched, SavedItem};
+
+/// The listings of a user profile
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum UserListing {
+    Submitted,
+    Comments,
+    /// Only available for the logged in user
+    Upvoted,
+    /// Only available for the logged in user
+    Downvoted,
+    /// Only available for the logged in user
+    Hidden,
+    /// Only available for the logged in user
+    Gilded,
+}
+
+impl UserListing {
+    /// The listings anyone can see
+    pub const PUBLIC: [UserListing; 2] = [UserListing::Submitted, UserListing::Comments];
+    pub const ALL: [UserListing; 6] = [
+        UserListing::Submitted,
+        UserListing::Comments,
+        UserListing::Upvoted,
+        UserListing::Downvoted,
+        UserListing::Hidden,
+        UserListing::Gilded,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UserListing::Submitted => "submitted",
+            UserListing::Comments => "comments",
+            UserListing::Upvoted => "upvoted",
+            UserListing::Downvoted => "downvoted",
+            UserListing::Hidden => "hidden",
+            UserListing::Gilded => "gilded",
+        }
+    }
+
+    /// Whether only the user themselves can see the listing
+    pub fn private(self) -> bool {
+        !UserListing::PUBLIC.contains(&self)
+    }
+
+    /// Whether the items are ordered by when they were created; votes, hidden and gilded items are ordered by
+    /// when that happened instead
+    fn ordered_by_creation(self) -> bool {
+        UserListing::PUBLIC.contains(&self)
+    }
+}
+
+/// The order of listings that are not sorted by `created_utc`, so paging never stops at the first old item
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ActionOrder(Sort);
+
+impl ListingSort for ActionOrder {
+    fn as_str(self) -> &'static str {
+        self.0.as_str()
+    }
+
+    fn newest_first(self) -> bool {
+        false
+    }
+
+    fn has_time_window(self) -> bool {
+        self.0.has_time_window()
+    }
+}
+
+/// Downloads one listing of the profile of `name` (Example: u/rexitTest); media is saved to `user/<name>/images`
+pub async fn download_user_listing(
+    client: &Client,
+    name: &str,
+    kind: UserListing,
+    listing: &ListingOptions,
+    options: PostOptions,
+    out: PathBuf,
+    redact: bool,
+) -> Fetched<SavedItem> {
+    let name = user_name(name);
+    if redact {
+        info!("Getting {} of [REDACTED]", kind.as_str());
+    } else {
+        info!("Getting {} of u/{name}", kind.as_str());
+    }
+
+    let page = |after: &str| {
+        format!(
+            "/user/{name}/{}.json?sort={}&{}",
+            kind.as_str(),
+            listing.sort.as_str(),
+            listing.query(after)
+        )
+    };
+
+    let folder = out.join(format!("user/{name}/images"));
+    if kind.ordered_by_creation() {
+        return listing::download_items(client, listing, page, options, &out, &folder, redact).await;
+    }
+
+    // Page to the end and only filter by the date range
+    let listing = ListingOptions {
+        sort: ActionOrder(listing.sort),
+        time: listing.time,
+        limit: listing.limit,
+        since: listing.since,
+        until: listing.until,
+    };
+    listing::download_items(client, &listing, page, options, &out, &folder, redact).await
+}
+
+/// The plain name of a user (without u/ or /user/)
+pub fn user_name(name: &str) -> &str {
+    let name = name.trim_matches('/');
+    name.strip_prefix("u/")
+        .or(name.strip_prefix("user/"))
+        .unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::UserListing;
+    use crate::ReAPI::SavedItem;
+
+    #[tokio::test]
+    async fn download_user_listing() {
+        let (client, _server) = super::super::mock::mock_client().await;
+
+        let items = super::download_user_listing(
+            &client,
+            "u/rexitTest",
+            UserListing::Comments,
+            &Default::default(),
+            Default::default(),
+            PathBuf::from("./out"),
+            false,
+        )
+        .await.items;
+
+        assert_eq!(items.len(), 1);
+        let SavedItem::Comment(comment) = &items[0] else {
+            panic!("Expected a comment");
+        };
+        assert_eq!(comment.body, "First!");
+
+        let options = super::PostOptions {
+            no_usernames: true,
+            ..Default::default()
+        };
+        let items = super::download_user_listing(&client, "rexitTest", UserListing::Comments, &Default::default(), options, PathBuf::from("./out"), false).await.items;
+        let SavedItem::Comment(comment) = &items[0] else {
+            panic!("Expected a comment");
+        };
+        assert_eq!(comment.author, "N/A");
+    }
+
+    #[tokio::test]
+    async fn download_user_listing_by_vote() {
+        let (client, _server) = super::super::mock::mock_client().await;
+
+        // The old post was upvoted last, so it comes first; the newer one after it is still in range
+        let listing = super::ListingOptions {
+            sort: super::Sort::New,
+            since: Some(chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2023, 5, 1, 0, 0, 0).unwrap()),
+            ..Default::default()
+        };
+        let items = super::download_user_listing(
+            &client,
+            "rexitTest",
+            UserListing::Upvoted,
+            &listing,
+            Default::default(),
+            PathBuf::from("./out"),
+            false,
+        )
+        .await.items;
+
+        assert_eq!(items.len(), 1);
+        let SavedItem::Post(post) = &items[0] else {
+            panic!("Expected a post");
+        };
+        assert_eq!(post.title, "New post upvoted earlier");
+    }
+
+    #[test]
+    fn user_name() {
+        assert_eq!(super::user_name("u/rexitTest"), "rexitTest");
+        assert_eq!(super::user_name("/user/rexitTest/"), "rexitTest");
+        assert_eq!(super::user_name("rexitTest"), "rexitTest");
+        assert!(UserListing::Upvoted.private());
+        assert!(!UserListing::Comments.private());
+    }
+}
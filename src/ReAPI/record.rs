@@ -0,0 +1,337 @@
+//! Records every request/response pair to a folder and replays them later, so a failing export can be reproduced without the account.
+//!
+//! Each exchange is saved as `NNNNN.json` (method, url, status, headers) next to the raw response body in `NNNNN.body`.
+//! Request bodies, auth headers and cookies are never saved.
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use console::style;
+use log::{debug, error, info};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+use super::Transport;
+
+/// Headers that carry credentials and are dropped from recordings
+const SECRET_HEADERS: [&str; 3] = ["authorization", "cookie", "set-cookie"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Exchange {
+    method: String,
+    url: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+}
+
+/// Transport that sends requests over the network and saves a copy of every exchange
+pub struct Recorder {
+    inner: reqwest::Client,
+    dir: PathBuf,
+    redact: bool,
+    count: AtomicUsize,
+    pseudonyms: Mutex<HashMap<String, String>>,
+}
+
+/// Transport that answers requests purely from a folder of recordings
+pub struct Replayer {
+    dir: PathBuf,
+    recordings: Mutex<HashMap<(String, String), VecDeque<String>>>,
+}
+
+impl super::Client {
+    /// Saves every request made from now on into `dir`. With `redact` usernames and user ids are replaced by placeholders.
+    pub fn record_to(&mut self, dir: PathBuf, redact: bool) {
+        std::fs::create_dir_all(&dir).expect("Error creating recording folder");
+
+        let recorder = Recorder {
+            inner: self.reqwest_client.clone(),
+            dir,
+            redact,
+            count: AtomicUsize::new(0),
+            pseudonyms: Mutex::new(HashMap::new()),
+        };
+        self.set_transport(recorder);
+    }
+
+    /// Answers every request from the recordings in `dir` instead of the network
+    pub fn replay_from(&mut self, dir: PathBuf) {
+        let replayer = Replayer::load(dir);
+        self.set_transport(replayer);
+    }
+}
+
+impl Recorder {
+    fn save(&self, exchange: Exchange, body: &[u8]) {
+        let id = format!("{:05}", self.count.fetch_add(1, Ordering::SeqCst));
+
+        let body = match std::str::from_utf8(body) {
+            Ok(text) => self.scrub(text).into_bytes(),
+            Err(_) => body.to_vec(),
+        };
+        let exchange = Exchange {
+            url: self.scrub_url(&exchange.url),
+            ..exchange
+        };
+
+        std::fs::write(
+            self.dir.join(&id).with_extension("json"),
+            serde_json::to_string_pretty(&exchange).unwrap(),
+        )
+        .expect("Error writing recording");
+        std::fs::write(self.dir.join(&id).with_extension("body"), body)
+            .expect("Error writing recording");
+
+        debug!("Recorded exchange {id}");
+    }
+
+    /// Removes tokens and (with --redact) replaces personal information with stable placeholders.
+    ///
+    /// The same value always gets the same placeholder, so the ids in the recorded URLs keep matching the ids in the bodies.
+    fn scrub(&self, text: &str) -> String {
+        let tokens = Regex::new(r#"("(?:accessToken|access_token)"\s*:\s*")[^"]*""#).unwrap();
+        let text = tokens.replace_all(text, r#"${1}[REDACTED]""#);
+
+        if !self.redact {
+            return text.into_owned();
+        }
+
+        let mut pseudonyms = self.pseudonyms.lock().unwrap();
+
+        let user_ids = Regex::new(r"t2_[a-z0-9]+").unwrap();
+        let text = user_ids.replace_all(&text, |caps: &Captures| {
+            pseudonym(&mut pseudonyms, &caps[0], "t2_redacted")
+        });
+
+        let names = Regex::new(r#"("(?:displayname|author|link_author)"\s*:\s*")([^"]*)""#).unwrap();
+        let text = names.replace_all(&text, |caps: &Captures| {
+            format!("{}{}\"", &caps[1], pseudonym(&mut pseudonyms, &caps[2], "user"))
+        });
+
+        text.into_owned()
+    }
+
+    /// Like [Recorder::scrub], also replacing the usernames in profile paths (Example: /user/<name>/comments.json)
+    /// with the placeholders used for them in the bodies, so a replay with the redacted name finds them
+    fn scrub_url(&self, url: &str) -> String {
+        let url = self.scrub(url);
+
+        if !self.redact {
+            return url;
+        }
+
+        let mut pseudonyms = self.pseudonyms.lock().unwrap();
+
+        let profiles = Regex::new(r"(/(?:user|u)/)([^/?#]+)").unwrap();
+        profiles
+            .replace_all(&url, |caps: &Captures| {
+                format!("{}{}", &caps[1], pseudonym(&mut pseudonyms, &caps[2], "user"))
+            })
+            .into_owned()
+    }
+}
+
+fn pseudonym(pseudonyms: &mut HashMap<String, String>, value: &str, prefix: &str) -> String {
+    let next = pseudonyms.len();
+    pseudonyms
+        .entry(value.to_owned())
+        .or_insert_with(|| format!("{prefix}{next}"))
+        .clone()
+}
+
+#[async_trait]
+impl Transport for Recorder {
+    async fn execute(&self, request: reqwest::Request) -> reqwest::Result<reqwest::Response> {
+        let method = request.method().to_string();
+        let url = request.url().to_string();
+
+        let response = self.inner.execute(request).await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+
+        let exchange = Exchange {
+            method,
+            url,
+            status: status.as_u16(),
+            headers: headers
+                .iter()
+                .filter(|(name, _)| !SECRET_HEADERS.contains(&name.as_str()))
+                .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+                .collect(),
+        };
+        self.save(exchange, &body);
+
+        // Hand the live response on unchanged
+        let mut builder = http::Response::builder().status(status);
+        for (name, value) in headers.iter() {
+            builder = builder.header(name, value);
+        }
+
+        Ok(reqwest::Response::from(builder.body(body).unwrap()))
+    }
+}
+
+impl Replayer {
+    fn load(dir: PathBuf) -> Replayer {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .expect("Error reading recording folder")
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+            .collect();
+        files.sort();
+
+        info!("Loaded {} recorded request(s)", files.len());
+
+        let mut recordings: HashMap<(String, String), VecDeque<String>> = HashMap::new();
+        for file in files {
+            let exchange: Exchange =
+                serde_json::from_str(&std::fs::read_to_string(&file).unwrap())
+                    .expect("Error parsing recording");
+            let id = file.file_stem().unwrap().to_string_lossy().to_string();
+
+            recordings
+                .entry((exchange.method, exchange.url))
+                .or_default()
+                .push_back(id);
+        }
+
+        Replayer {
+            dir,
+            recordings: Mutex::new(recordings),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for Replayer {
+    async fn execute(&self, request: reqwest::Request) -> reqwest::Result<reqwest::Response> {
+        let key = (request.method().to_string(), request.url().to_string());
+
+        // Repeated requests are answered in the order they were recorded; the last answer is reused after that
+        let id = {
+            let mut recordings = self.recordings.lock().unwrap();
+            match recordings.get_mut(&key) {
+                Some(ids) if ids.len() > 1 => ids.pop_front(),
+                Some(ids) => ids.front().cloned(),
+                None => None,
+            }
+        };
+
+        let Some(id) = id else {
+            println!("{}", style("Request missing from the recording").red().bold());
+            error!("No recording for {} {}", key.0, key.1);
+            crate::exit!(1, "Request missing from the recording");
+        };
+
+        let exchange: Exchange =
+            serde_json::from_str(&std::fs::read_to_string(self.dir.join(&id).with_extension("json")).unwrap())
+                .expect("Error parsing recording");
+        let body = std::fs::read(self.dir.join(&id).with_extension("body")).expect("Error reading recording");
+
+        let mut builder = http::Response::builder().status(exchange.status);
+        for (name, value) in exchange.headers {
+            builder = builder.header(name, value);
+        }
+
+        Ok(reqwest::Response::from(builder.body(body).unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::super::{mock, new_client};
+
+    #[tokio::test]
+    async fn record_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Record against the mock server
+        let (mut client, server) = mock::mock_client().await;
+        client.record_to(dir.path().to_path_buf(), true);
+
+        let recorded = super::super::download_subreddit(&client, "r/rexitTest".to_owned(), &Default::default(), Default::default(), PathBuf::from("./out"), false).await.items;
+        let uri = server.uri();
+        drop(server);
+
+        // No auth header is written to disk
+        for entry in std::fs::read_dir(dir.path()).unwrap() {
+            let contents = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            assert!(!contents.contains(mock::TOKEN));
+        }
+
+        // Replay with the mock server gone
+        let mut client = new_client(false);
+        client.set_reddit_url(&uri);
+        client.replay_from(dir.path().to_path_buf());
+
+        let replayed = super::super::download_subreddit(&client, "r/rexitTest".to_owned(), &Default::default(), Default::default(), PathBuf::from("./out"), false).await.items;
+
+        assert_eq!(recorded.len(), replayed.len());
+        assert_eq!(recorded[1].title, replayed[1].title);
+    }
+
+    #[tokio::test]
+    async fn record_and_replay_user() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let (mut client, server) = mock::mock_client().await;
+        client.record_to(dir.path().to_path_buf(), true);
+
+        let kind = crate::ReAPI::UserListing::Comments;
+        super::super::download_user_listing(&client, "rexitTest", kind, &Default::default(), Default::default(), PathBuf::from("./out"), false).await;
+        let uri = server.uri();
+        drop(server);
+
+        // Neither the URLs nor the bodies contain the username
+        for entry in std::fs::read_dir(dir.path()).unwrap() {
+            let contents = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            assert!(!contents.contains("/user/rexitTest"), "{contents}");
+            assert!(!contents.contains(r#""author": "rexitTest""#) && !contents.contains(r#""author":"rexitTest""#));
+        }
+
+        // Replaying under the placeholder name (as whoami returns it) finds the recording
+        let mut client = new_client(false);
+        client.set_reddit_url(&uri);
+        client.replay_from(dir.path().to_path_buf());
+
+        let replayed = super::super::download_user_listing(&client, "user0", kind, &Default::default(), Default::default(), PathBuf::from("./out"), false).await.items;
+
+        assert_eq!(replayed.len(), 1);
+        let crate::ReAPI::SavedItem::Comment(comment) = &replayed[0] else {
+            panic!("Expected a comment");
+        };
+        assert_eq!(comment.author, "user0");
+    }
+
+    #[test]
+    fn scrub() {
+        let recorder = super::Recorder {
+            inner: reqwest::Client::new(),
+            dir: PathBuf::new(),
+            redact: true,
+            count: Default::default(),
+            pseudonyms: Default::default(),
+        };
+
+        let scrubbed = recorder.scrub(
+            r#"{"accessToken":"secret","sender":"@t2_abc:reddit.com","displayname":"rexitTest","author":"rexitTest"}"#,
+        );
+
+        assert_eq!(
+            scrubbed,
+            r#"{"accessToken":"[REDACTED]","sender":"@t2_redacted0:reddit.com","displayname":"user1","author":"user1"}"#
+        );
+        // Same id, same placeholder
+        assert_eq!(recorder.scrub("/profile/@t2_abc:reddit.com"), "/profile/@t2_redacted0:reddit.com");
+        // Profile paths use the same placeholders as the bodies
+        assert_eq!(
+            recorder.scrub_url("https://oauth.reddit.com/user/rexitTest/comments.json?after="),
+            "https://oauth.reddit.com/user/user1/comments.json?after="
+        );
+    }
+}
@@ -1,76 +1,222 @@
 use std::path::PathBuf;
 
-use super::Client;
-use log::{info, trace, warn, debug};
+use super::messages::MessageFilter;
+use super::{Client, Fetched};
+use crate::progress;
+use chrono::{DateTime, Utc};
+use log::{info, debug, warn};
 use serde::Serialize;
 use serde_json::Value;
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Room {
     pub id: String,
+    /// Subject of private message conversations
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     pub(crate) messages: Option<Vec<super::Message>>,
 }
 
 impl Room {
-    async fn download(id: String, client: &Client, image_download: bool, no_usernames: bool, out: PathBuf, redact: bool) -> Room {
-        Room {
-            id: id.clone(),
-            messages: download_messages(&client, id.clone(), image_download, no_usernames, out, redact).await,
-        }
+    async fn download(
+        info: &RoomInfo,
+        client: &Client,
+        image_download: bool,
+        no_usernames: bool,
+        out: PathBuf,
+        redact: bool,
+        filter: &MessageFilter,
+    ) -> (Room, Option<String>) {
+        let messages = super::messages::list_messages(client, info.id.clone(), image_download, no_usernames, out, redact, filter).await;
+        let room = Room {
+            id: info.id.clone(),
+            name: info.name.clone(),
+            messages: Some(messages.items),
+        };
+
+        (room, messages.error)
     }
 
     pub fn messages(&self) -> Vec<super::Message> {
-        return self.messages.clone().unwrap();
+        self.messages.clone().unwrap()
     }
 }
 
-async fn download_messages(
-    client: &Client,
-    id: String,
-    image_download: bool,
-    no_usernames: bool,
-    out: PathBuf,
-    redact: bool
-) -> Option<Vec<super::Message>> {
-    Some(super::messages::list_messages(client, id, image_download, no_usernames, out, redact).await)
+/// A joined room before its messages are downloaded
+#[derive(Debug, Clone, Default)]
+pub struct RoomInfo {
+    pub id: String,
+    pub name: Option<String>,
+    /// Display names of the joined members
+    pub members: Vec<String>,
+    /// Time of the newest message
+    pub last_activity: Option<DateTime<Utc>>,
+}
+
+/// Which rooms to export; empty filters keep every room
+#[derive(Debug, Clone, Default)]
+pub struct RoomFilter {
+    /// Only export these rooms (ids or names)
+    pub rooms: Vec<String>,
+    /// Skip these rooms (ids or names)
+    pub exclude: Vec<String>,
+    /// Only export rooms with one of these members (usernames)
+    pub members: Vec<String>,
+}
+
+impl RoomFilter {
+    /// Whether names or members are needed to filter
+    pub fn is_empty(&self) -> bool {
+        self.rooms.is_empty() && self.exclude.is_empty() && self.members.is_empty()
+    }
+
+    pub fn matches(&self, room: &RoomInfo) -> bool {
+        let is = |wanted: &String| {
+            *wanted == room.id || room.name.as_ref().is_some_and(|name| name.eq_ignore_ascii_case(wanted))
+        };
+        let has_member = |member: &String| {
+            let member = member.trim_start_matches("u/");
+            room.members.iter().any(|name| name.eq_ignore_ascii_case(member))
+        };
+
+        (self.rooms.is_empty() || self.rooms.iter().any(is))
+            && !self.exclude.iter().any(is)
+            && (self.members.is_empty() || self.members.iter().any(has_member))
+    }
 }
 
-/// Returns list of all rooms that the user is joined to as per [SPEC](https://spec.matrix.org/v1.6/client-server-api/#get_matrixclientv3directorylistroomroomid)
-pub async fn download_rooms(client: &Client, image_download: bool, no_usernames: bool, out: PathBuf, redact: bool) -> Vec<Room> {
+/// Returns list of all rooms that the user is joined to as per [SPEC](https://spec.matrix.org/v1.6/client-server-api/#get_matrixclientv3joined_rooms);
+/// with `details` their names, members and last activity are looked up as well
+pub async fn list_rooms(client: &Client, details: bool) -> Result<Vec<RoomInfo>, String> {
+    let request = client
+        .get(client.matrix("/_matrix/client/v3/joined_rooms"))
+        .header("Authorization", format!("Bearer {}", client.bearer_token()));
     let resp = client
-        .reqwest_client
-        .get("https://matrix.redditspace.com/_matrix/client/v3/joined_rooms")
-        .header("Authorization", format!("Bearer {}", client.bearer_token()))
-        .send()
+        .send(request)
         .await
-        .expect("Failed to send HTTP request; to obtain rooms");
+        .map_err(|error| format!("Failed to send HTTP request; to obtain rooms: {error}"))?;
 
       
       // Parse json
+      let text = resp.text().await.map_err(|error| format!("Failed to read rooms: {error}"))?;
       let json: Value =
-      serde_json::from_str(&resp.text().await.unwrap()).expect("Error parsing Rooms list JSON");
+      serde_json::from_str(&text).map_err(|error| format!("Error parsing Rooms list JSON: {error}"))?;
       
       debug!("downloadRoomsResponse: {:?}", json);
 
     // Read rooms from json
-    let rooms = json["joined_rooms"]
+    let ids = json["joined_rooms"]
         .as_array()
-        .expect("Error parsing array; likely error with login")
+        .ok_or("Error parsing array; likely error with login")?
         .to_owned();
 
-    // Move rooms into a Vec<Room>
-    let rooms = rooms.iter().map(move |room| {
-        Room::download(room.to_string().replace("\"", ""), client, image_download, no_usernames, out.to_owned(), redact)
-    });
+    let mut rooms: Vec<RoomInfo> = vec![];
+    for id in ids {
+        let mut room = RoomInfo {
+            id: id.as_str().unwrap_or_default().to_owned(),
+            ..Default::default()
+        };
+
+        if details {
+            room.name = room_name(client, &room.id).await;
+            room.members = room_members(client, &room.id).await;
+            room.last_activity = last_activity(client, &room.id).await;
+        }
+
+        rooms.push(room);
+    }
+
+    info!("Found {} room(s) ", rooms.len());
+
+    Ok(rooms)
+}
+
+/// Downloads the messages of the given rooms that match the filter; a room that fails keeps the messages before the error
+pub async fn download_rooms(
+    client: &Client,
+    rooms: &[RoomInfo],
+    image_download: bool,
+    no_usernames: bool,
+    out: PathBuf,
+    redact: bool,
+    filter: &MessageFilter,
+) -> Fetched<Room> {
+    let mut downloaded: Vec<Room> = vec![];
+    let mut errors: Vec<String> = vec![];
+    for (index, room) in rooms.iter().enumerate() {
+        let label = room.name.clone().unwrap_or(room.id.clone());
+        progress::stage(format!("room {}/{} ({label})", index + 1, rooms.len()));
+        let (room, error) = Room::download(room, client, image_download, no_usernames, out.to_owned(), redact, filter).await;
+
+        let counts = progress::stage_counts();
+        info!("Room {}: {} message(s), {} media", room.id, counts.messages, counts.media + counts.skipped);
+        if let Some(error) = error {
+            errors.push(format!("{}: {error}", room.id));
+        }
+        downloaded.push(room);
+    }
+
+    progress::finish();
 
-    let mut rooms_2: Vec<Room> = vec![];
-    for room in rooms {
-        rooms_2.push(room.await);
+    Fetched {
+        items: downloaded,
+        error: (!errors.is_empty()).then(|| errors.join("; ")),
     }
+}
+
+async fn get_json(client: &Client, path: &str) -> Option<Value> {
+    let request = client
+        .get(client.matrix(path))
+        .header("Authorization", format!("Bearer {}", client.bearer_token()));
+    let response = match client.send(request).await {
+        Ok(response) => response,
+        Err(error) => {
+            warn!("Failed to send HTTP request; to obtain room details: {error}");
+            return None;
+        }
+    };
+
+    // Rooms without a name answer with 404
+    if !response.status().is_success() {
+        debug!("No room details at {path}: {}", response.status());
+        return None;
+    }
+
+    match serde_json::from_str(&response.text().await.unwrap_or_default()) {
+        Ok(json) => Some(json),
+        Err(error) => {
+            warn!("Failed to parse room details: {error}");
+            None
+        }
+    }
+}
+
+async fn room_name(client: &Client, id: &str) -> Option<String> {
+    let json = get_json(client, &format!("/_matrix/client/v3/rooms/{id}/state/m.room.name")).await?;
+
+    json["name"].as_str().filter(|name| !name.is_empty()).map(str::to_owned)
+}
+
+async fn room_members(client: &Client, id: &str) -> Vec<String> {
+    let Some(json) = get_json(client, &format!("/_matrix/client/v3/rooms/{id}/joined_members")).await else {
+        return vec![];
+    };
+
+    let mut members: Vec<String> = json["joined"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(user_id, member)| member["display_name"].as_str().unwrap_or(user_id).to_owned())
+        .collect();
+    members.sort();
+
+    members
+}
 
-    info!("Found {} room(s) ", rooms_2.len());
+async fn last_activity(client: &Client, id: &str) -> Option<DateTime<Utc>> {
+    let json = get_json(client, &format!("/_matrix/client/r0/rooms/{id}/messages?limit=1&dir=b")).await?;
 
-    return rooms_2;
+    json["chunk"][0]["origin_server_ts"].as_i64().and_then(DateTime::from_timestamp_millis)
 }
 
 #[cfg(test)]
@@ -85,9 +231,63 @@ mod tests {
 
         client.login(username, password).await;
 
-        let rooms = super::download_rooms(&client, true, false, PathBuf::from("./out"), false);
+        let rooms = super::list_rooms(&client, true).await.unwrap();
+        let rooms = super::download_rooms(&client, &rooms, true, false, PathBuf::from("./out"), false, &Default::default()).await.items;
+
+        println!("{:?}", rooms);
+    }
+
+    #[tokio::test]
+    async fn list_rooms_offline() {
+        let (client, _server) = super::super::mock::mock_client().await;
+
+        let rooms = super::list_rooms(&client, false).await.unwrap();
+        let rooms = super::download_rooms(&client, &rooms, false, false, PathBuf::from("./out"), false, &Default::default()).await.items;
+
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(rooms[0].id, "!mockroom0001:reddit.com");
+        assert_eq!(rooms[0].messages().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn list_rooms_details() {
+        let (client, _server) = super::super::mock::mock_client().await;
+
+        let rooms = super::list_rooms(&client, true).await.unwrap();
+
+        assert_eq!(rooms[0].name.as_deref(), Some("Mock chat"));
+        assert_eq!(rooms[0].members, vec!["otherUser", "rexitTest"]);
+        assert_eq!(rooms[0].last_activity.unwrap().timestamp(), 1683402900);
+
+        // The name is exported with the messages
+        let downloaded = super::download_rooms(&client, &rooms, false, true, PathBuf::from("./out"), false, &Default::default()).await.items;
+        assert_eq!(downloaded[0].name.as_deref(), Some("Mock chat"));
+
+        // Without details only the ids are listed
+        let rooms = super::list_rooms(&client, false).await.unwrap();
+        assert_eq!(rooms[0].name, None);
+    }
+
+    #[test]
+    fn room_filter() {
+        let room = super::RoomInfo {
+            id: "!mockroom0001:reddit.com".to_owned(),
+            name: Some("Mock chat".to_owned()),
+            members: vec!["otherUser".to_owned(), "rexitTest".to_owned()],
+            last_activity: None,
+        };
+        let filter = |rooms: &[&str], exclude: &[&str], members: &[&str]| super::RoomFilter {
+            rooms: rooms.iter().map(|room| room.to_string()).collect(),
+            exclude: exclude.iter().map(|room| room.to_string()).collect(),
+            members: members.iter().map(|member| member.to_string()).collect(),
+        };
 
-        println!("{:?}", rooms.await);
+        assert!(filter(&[], &[], &[]).matches(&room));
+        assert!(filter(&["mock chat"], &[], &[]).matches(&room));
+        assert!(filter(&["!mockroom0001:reddit.com"], &[], &["u/OtherUser"]).matches(&room));
+        assert!(!filter(&["Other chat"], &[], &[]).matches(&room));
+        assert!(!filter(&[], &["Mock chat"], &[]).matches(&room));
+        assert!(!filter(&[], &[], &["someoneElse"]).matches(&room));
     }
 
     fn get_login() -> (String, String) {
@@ -1,110 +1,143 @@
 use std::path::PathBuf;
 
-use super::{images, Client};
+use super::comments::CommentData;
+use super::listing::{self, ListingOptions, Sort};
+use super::posts::PostOptions;
+use super::{Client, Fetched, Post};
 use log::info;
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
+use serde_json::Value;
 
+/// A post or comment of a listing (saved items, user history)
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SavedList {
-    posts: Vec<SavedPost>,
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SavedItem {
+    Post(Post),
+    Comment(SavedComment),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SavedPost {
-    pub title: String,
+pub struct SavedComment {
+    /// Fullname of the comment (Example: t1_jj1aaaa)
+    #[serde(default)]
+    pub id: String,
+    pub author: String,
+    pub body: String,
+    pub score: i64,
+    /// Unix timestamp (UTC)
+    pub created: f64,
     pub subreddit_name: String,
+    /// Title of the post the comment was made on
+    pub link_title: String,
+    pub link_permalink: String,
     pub permalink: String,
-    pub img_url: Vec<String>,
-    pub body_text: String,
 }
 
-pub async fn download_saved_posts(client: &Client, image_download: bool, out: PathBuf, redact: bool) -> Vec<SavedPost> {
+impl SavedComment {
+    /// Builds a comment from the `data` of a listing child; broken ones are skipped
+    pub(crate) fn from_data(data: &Value) -> Option<SavedComment> {
+        let data = CommentData::parse(data)?;
+
+        Some(SavedComment {
+            id: data.name,
+            author: data.author,
+            body: data.body,
+            score: data.score,
+            created: data.created_utc,
+            subreddit_name: data.subreddit_name_prefixed,
+            link_title: data.link_title,
+            link_permalink: data.link_permalink,
+            permalink: data.permalink,
+        })
+    }
+}
+
+/// Downloads the saved posts and comments; the sort and time window of `listing` are ignored
+pub async fn download_saved_posts(
+    client: &Client,
+    listing: &ListingOptions,
+    options: PostOptions,
+    out: PathBuf,
+    redact: bool,
+) -> Fetched<SavedItem> {
     info!("Getting Saved Posts");
 
-    let mut after_token = String::new();
-    let mut saved_list: Vec<SavedPost> = Vec::<SavedPost>::new();
+    // Saved items are ordered by when they were saved, so the date range only filters and never stops paging
+    let listing = ListingOptions {
+        sort: Sort::Hot,
+        time: None,
+        ..listing.clone()
+    };
+    let page = |after: &str| format!("/saved.json?{}", listing.query(after));
+
+    listing::download_items(client, &listing, page, options, &out, &out.join("saved_posts/images"), redact).await
+}
 
-    loop {
-        let url = format!("https://www.reddit.com/saved.json?after={after_token}");
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
 
-        let response = client
-            .reqwest_client
-            .get(url)
-            .send()
+    use super::SavedItem;
+
+    #[tokio::test]
+    async fn download_saved_posts_offline() {
+        let (client, _server) = super::super::mock::mock_client().await;
+
+        let posts = super::download_saved_posts(&client, &Default::default(), Default::default(), PathBuf::from("./out"), false)
             .await
-            .expect("Failed to send HTTP request");
-
-        let saved_posts: Result<Value, _> =
-            serde_json::from_str(response.text().await.unwrap().as_str());
-        if saved_posts.is_err() {
-            return vec![];
-        }
-        let saved_posts = saved_posts.unwrap();
-
-        // Iterates over all saved posts in the response array
-        for post in saved_posts["data"]["children"].as_array().unwrap() {
-            // Get all image urls
-            let mut images = Vec::<String>::new();
-
-            // If post has images
-            if !post["data"]["preview"].is_null() {
-                for image in post["data"]["preview"]["images"].as_array().unwrap() {
-                    // The preview URL is HTML encoded (&amp; etc) so we need to decode it
-                    let url = image["source"]["url"].as_str().unwrap().to_string();
-                    let url = html_escape::decode_html_entities(&url);
-
-                    if image_download {
-                        images::get_image(
-                            &client,
-                            url.to_string(),
-                            out.clone(),
-                            &std::path::PathBuf::from("./out/saved_posts/images"),
-                            redact,
-                        )
-                        .await;
-                    }
-
-                    images.push(url.to_string())
-                }
-            }
-
-            // Link posts require extra massaging to make work
-            if !post["data"]["link_title"].is_null() {
-                let post = SavedPost {
-                    title: post["data"]["link_title"].as_str().unwrap().to_string(),
-                    subreddit_name: post["data"]["subreddit_name_prefixed"]
-                        .as_str()
-                        .unwrap()
-                        .to_string(),
-                    permalink: post["data"]["permalink"].as_str().unwrap().to_string(),
-                    img_url: images,
-                    body_text: post["data"]["selftext"].as_str().unwrap().to_string(),
-                };
-
-                saved_list.push(post);
-            } else {
-                // Normal text post
-                let post = SavedPost {
-                    title: post["data"]["title"].as_str().unwrap().to_string(),
-                    subreddit_name: post["data"]["subreddit_name_prefixed"]
-                        .as_str()
-                        .unwrap()
-                        .to_string(),
-                    permalink: post["data"]["permalink"].as_str().unwrap().to_string(),
-                    img_url: images,
-                    body_text: post["data"]["selftext"].as_str().unwrap().to_string(),
-                };
-
-                saved_list.push(post);
-            }
-        }
-        if saved_posts["data"]["after"] == json!(null) {
-            break;
-        }
-
-        after_token = saved_posts["data"]["after"].as_str().unwrap().to_string();
+            .items;
+
+        assert_eq!(posts.len(), 3);
+        let SavedItem::Post(post) = &posts[0] else {
+            panic!("Expected a post");
+        };
+        assert_eq!(post.title, "Da fehlt doch was");
+        assert_eq!(post.subreddit_name, "r/hamburg");
+        // The preview URL is decoded from its HTML entities
+        assert_eq!(
+            post.img_url,
+            vec!["https://preview.redd.it/mockpreview01.jpg?width=640&format=pjpg&auto=webp"]
+        );
+        let SavedItem::Post(post) = &posts[1] else {
+            panic!("Expected a post");
+        };
+        assert_eq!(post.body_text, "hihi");
+        assert!(post.img_url.is_empty());
+
+        // Saved comments keep their own text and the post they belong to
+        let SavedItem::Comment(comment) = &posts[2] else {
+            panic!("Expected a comment");
+        };
+        assert_eq!(comment.id, "t1_jj1aaaa");
+        assert_eq!(comment.body, "First!");
+        assert_eq!(comment.author, "rexitTest");
+        assert_eq!(comment.link_title, "Testing Rexit");
+
+        // The limit and date range of `all` apply to saved items too
+        let listing = super::ListingOptions {
+            limit: Some(1),
+            ..Default::default()
+        };
+        let posts = super::download_saved_posts(&client, &listing, Default::default(), PathBuf::from("./out"), false).await;
+        assert_eq!(posts.items.len(), 1);
+
+        let listing = super::ListingOptions {
+            since: Some(chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2023, 5, 1, 0, 0, 0).unwrap()),
+            ..Default::default()
+        };
+        let posts = super::download_saved_posts(&client, &listing, Default::default(), PathBuf::from("./out"), false).await;
+        assert_eq!(posts.items.len(), 1);
+        assert_eq!(posts.error, None);
     }
 
-    return saved_list;
+    #[test]
+    fn from_data() {
+        let data = serde_json::json!({ "name": "t1_x", "body": "Removed", "permalink": "/r/x/comments/y/z/x/" });
+
+        // Deleted comments have no author, broken ones are skipped
+        let comment = super::SavedComment::from_data(&data).unwrap();
+        assert_eq!(comment.author, "[deleted]");
+        assert_eq!(comment.link_title, "");
+        assert!(super::SavedComment::from_data(&serde_json::json!({ "body": "No name" })).is_none());
+    }
 }
@@ -0,0 +1,76 @@
+//! Downloads the posts matching a search, on all of Reddit or in one subreddit.
+use std::path::PathBuf;
+
+use log::info;
+use url::form_urlencoded;
+
+use super::listing::{self, ListingOptions, ListingSort, SearchSort};
+use super::posts::PostOptions;
+use super::subreddit;
+use super::{Client, Fetched, Post};
+
+/// Searches for posts matching `query`, only in `subreddit` (Example: r/redditdev) if given
+pub async fn download_search(
+    client: &Client,
+    query: &str,
+    subreddit: Option<&str>,
+    listing: &ListingOptions<SearchSort>,
+    options: PostOptions,
+    out: PathBuf,
+    redact: bool,
+) -> Fetched<Post> {
+    info!("Searching posts");
+
+    let query: String = form_urlencoded::byte_serialize(query.as_bytes()).collect();
+    let base = match subreddit {
+        Some(subreddit) => format!("/{}/search.json?restrict_sr=1&", subreddit::subreddit_path(subreddit)),
+        None => "/search.json?".to_owned(),
+    };
+    let page = |after: &str| {
+        format!(
+            "{base}q={query}&type=link&sort={}&{}",
+            listing.sort.as_str(),
+            listing.query(after)
+        )
+    };
+
+    listing::download_posts(client, listing, page, options, &out, &out.join("search/images"), redact).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn download_search_offline() {
+        let (client, _server) = super::super::mock::mock_client().await;
+
+        let posts = super::download_search(
+            &client,
+            "Testing Rexit",
+            Some("r/rexitTest"),
+            &Default::default(),
+            Default::default(),
+            PathBuf::from("./out"),
+            false,
+        )
+        .await.items;
+
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].title, "Testing Rexit");
+
+        // Bare subreddit names work like with the subreddit command
+        let posts = super::download_search(
+            &client,
+            "Testing Rexit",
+            Some("rexitTest"),
+            &Default::default(),
+            Default::default(),
+            PathBuf::from("./out"),
+            false,
+        )
+        .await;
+        assert_eq!(posts.error, None);
+        assert_eq!(posts.items.len(), 1);
+    }
+}
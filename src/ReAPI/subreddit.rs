@@ -1,116 +1,204 @@
 use std::path::PathBuf;
 
-use super::{images, Client};
+use super::listing::{self, ListingOptions};
+use super::posts::PostOptions;
+use super::{Client, Fetched, Post};
 use log::info;
-use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SavedList {
-    posts: Vec<Post>,
+/// Splits the names given on the command line into single subreddits (Example: r/a+b -> r/a, r/b) and multireddits (Example: /user/x/m/name)
+pub fn expand_names(names: &[String]) -> Vec<String> {
+    let mut expanded = vec![];
+
+    for name in names {
+        let name = name.trim_matches('/');
+
+        if let Some(multireddit) = name.strip_prefix("u/").or(name.strip_prefix("user/")) {
+            expanded.push(format!("user/{multireddit}"));
+            continue;
+        }
+
+        let name = name.strip_prefix("r/").unwrap_or(name);
+        for subreddit in name.split('+').filter(|subreddit| !subreddit.is_empty()) {
+            let subreddit = format!("r/{subreddit}");
+            if !expanded.contains(&subreddit) {
+                expanded.push(subreddit);
+            }
+        }
+    }
+
+    expanded
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Post {
-    pub title: String,
-    pub subreddit_name: String,
-    pub permalink: String,
-    pub img_url: Vec<String>,
-    pub body_text: String,
+/// Whether the name is a multireddit (Example: user/x/m/name)
+pub fn is_multireddit(subreddit_name: &str) -> bool {
+    subreddit_name.trim_matches('/').starts_with("user/")
 }
 
+/// Path of a subreddit or multireddit, accepting the same names as [expand_names] (Example: redditdev -> r/redditdev)
+pub(crate) fn subreddit_path(name: &str) -> String {
+    let name = name.trim_matches('/');
+
+    match name.strip_prefix("u/").or(name.strip_prefix("user/")) {
+        Some(multireddit) => format!("user/{multireddit}"),
+        None => format!("r/{}", name.strip_prefix("r/").unwrap_or(name)),
+    }
+}
+
+/// Folder below `subreddit` the exports of a subreddit or multireddit go to (Example: r/redditdev -> redditdev, user/x/m/name -> user_x_m_name)
+pub fn folder_name(subreddit_name: &str) -> String {
+    let name = subreddit_name.trim_matches('/');
+    name.strip_prefix("r/").unwrap_or(name).replace('/', "_")
+}
+
+/// Downloads the posts of a subreddit or multireddit; media is saved to `subreddit/<folder_name>/images`
 pub async fn download_subreddit(
     client: &Client,
     subreddit_name: String,
-    image_download: bool,
+    listing: &ListingOptions,
+    options: PostOptions,
     out: PathBuf,
     redact: bool,
-) -> Vec<Post> {
+) -> Fetched<Post> {
     info!("Getting subreddit");
 
-    let mut after_token = String::new();
-    let mut saved_list: Vec<Post> = Vec::<Post>::new();
+    let base = format!("/{}", subreddit_name.trim_matches('/'));
+    let page = |after: &str| listing.path(&base, after);
+
+    let folder = out.join(format!("subreddit/{}/images", folder_name(&subreddit_name)));
+    listing::download_posts(client, listing, page, options, &out, &folder, redact).await
+}
 
-    loop {
-        let url = format!("https://www.reddit.com/{subreddit_name}.json?after={after_token}");
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn download_subreddit_offline() {
+        let (client, _server) = super::super::mock::mock_client().await;
+
+        let posts = super::download_subreddit(&client, "r/rexitTest".to_owned(), &Default::default(), Default::default(), PathBuf::from("./out"), false).await.items;
+
+        // Both pages of the listing are followed
+        assert_eq!(posts.len(), 2);
+        assert_eq!(posts[0].title, "Second post");
+        assert_eq!(posts[1].title, "Testing Rexit");
+        assert_eq!(posts[1].permalink, "/r/rexitTest/comments/13a9rn6/testing_rexit/");
+        assert_eq!(posts[1].img_url.len(), 1);
+        assert!(posts[1].comments.is_empty());
+        assert_eq!(posts[1].id, "t3_13a9rn6");
+        assert_eq!(posts[1].author, "rexitTest");
+        assert_eq!(posts[1].num_comments, 4);
+        // Text posts have no link
+        assert_eq!(posts[1].url, None);
+    }
 
-        let response = client
-            .reqwest_client
-            .get(url)
-            .send()
-            .await
-            .expect("Failed to send HTTP request");
+    #[tokio::test]
+    async fn download_subreddit_failing() {
+        use wiremock::matchers::{path, query_param};
+        use wiremock::{Mock, ResponseTemplate};
+
+        let (client, server) = super::super::mock::mock_client().await;
+        let forbidden = ResponseTemplate::new(403).set_body_json(serde_json::json!({ "message": "Forbidden", "error": 403 }));
+
+        // The first page is kept if a later one fails
+        Mock::given(path("/r/rexitTest/new.json"))
+            .and(query_param("after", ""))
+            .respond_with(ResponseTemplate::new(200).set_body_json(super::super::mock::fixture("subreddit_page1")))
+            .mount(&server)
+            .await;
+        Mock::given(path("/r/rexitTest/new.json"))
+            .and(query_param("after", "t3_13a9rn6"))
+            .respond_with(forbidden.clone())
+            .mount(&server)
+            .await;
+
+        let listing = super::ListingOptions {
+            sort: super::super::Sort::New,
+            ..Default::default()
+        };
+        let posts = super::download_subreddit(&client, "r/rexitTest".to_owned(), &listing, Default::default(), PathBuf::from("./out"), false).await;
+        assert_eq!(posts.items.len(), 1);
+        assert_eq!(posts.error.as_deref(), Some("Reddit answered 403 Forbidden: Forbidden"));
+
+        // Private subreddits answer with an error instead of a listing
+        Mock::given(path("/r/private/hot.json")).respond_with(forbidden).mount(&server).await;
+        let posts = super::download_subreddit(&client, "r/private".to_owned(), &Default::default(), Default::default(), PathBuf::from("./out"), false).await;
+        assert!(posts.items.is_empty());
+        assert!(posts.error.is_some());
+
+        // So do unknown paths of the mock server
+        let posts = super::download_subreddit(&client, "r/unknown".to_owned(), &Default::default(), Default::default(), PathBuf::from("./out"), false).await;
+        assert!(posts.error.is_some());
+    }
 
-        let saved_posts: Result<Value, _> =
-            serde_json::from_str(response.text().await.unwrap().as_str());
-        if saved_posts.is_err() {
-            return vec![];
-        }
-        let saved_posts = saved_posts.unwrap();
-
-        // Iterates over all saved posts in the response array
-        for post in saved_posts["data"]["children"].as_array().unwrap() {
-            // Get all image urls
-            let mut images = Vec::<String>::new();
-
-            // If post has images
-            if !post["data"]["preview"].is_null() {
-                for image in post["data"]["preview"]["images"].as_array().unwrap() {
-                    // The preview URL is HTML encoded (&amp; etc) so we need to decode it
-                    let url = image["source"]["url"].as_str().unwrap().to_string();
-                    let url = html_escape::decode_html_entities(&url);
-
-                    if image_download {
-                        images::get_image(
-                            &client,
-                            url.to_string(),
-                            out.clone(),
-                            &std::path::PathBuf::from("./out/subreddit/images"),
-                            redact,
-                        )
-                        .await;
-                    }
-
-                    images.push(url.to_string())
-                }
-            }
+    #[test]
+    fn expand_names() {
+        let names = ["r/a+b".to_owned(), "/r/c/".to_owned(), "a".to_owned(), "/user/x/m/name".to_owned()];
 
-            // Link posts require extra massaging to make work
-            if !post["data"]["link_title"].is_null() {
-                let post = Post {
-                    title: post["data"]["link_title"].as_str().unwrap().to_string(),
-                    subreddit_name: post["data"]["subreddit_name_prefixed"]
-                        .as_str()
-                        .unwrap()
-                        .to_string(),
-                    permalink: post["data"]["permalink"].as_str().unwrap().to_string(),
-                    img_url: images,
-                    body_text: post["data"]["selftext"].as_str().unwrap().to_string(),
-                };
-
-                saved_list.push(post);
-            } else {
-                // Normal text post
-                let post = Post {
-                    title: post["data"]["title"].as_str().unwrap().to_string(),
-                    subreddit_name: post["data"]["subreddit_name_prefixed"]
-                        .as_str()
-                        .unwrap()
-                        .to_string(),
-                    permalink: post["data"]["permalink"].as_str().unwrap().to_string(),
-                    img_url: images,
-                    body_text: post["data"]["selftext"].as_str().unwrap().to_string(),
-                };
-
-                saved_list.push(post);
-            }
-        }
-        if saved_posts["data"]["after"] == json!(null) {
-            break;
-        }
+        assert_eq!(super::expand_names(&names), vec!["r/a", "r/b", "r/c", "user/x/m/name"]);
+        assert_eq!(super::folder_name("r/a"), "a");
+        assert_eq!(super::folder_name("user/x/m/name"), "user_x_m_name");
+        assert!(super::is_multireddit("user/x/m/name"));
+        assert!(!super::is_multireddit("r/a"));
 
-        after_token = saved_posts["data"]["after"].as_str().unwrap().to_string();
+        assert_eq!(super::subreddit_path("redditdev"), "r/redditdev");
+        assert_eq!(super::subreddit_path("/r/a+b/"), "r/a+b");
+        assert_eq!(super::subreddit_path("u/x/m/name"), "user/x/m/name");
     }
 
-    return saved_list;
+    #[tokio::test]
+    async fn download_multireddit() {
+        let (client, _server) = super::super::mock::mock_client().await;
+
+        let posts = super::download_subreddit(&client, "user/rexitTest/m/mock".to_owned(), &Default::default(), Default::default(), PathBuf::from("./out"), false).await.items;
+
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].title, "Testing Rexit");
+    }
+
+    #[tokio::test]
+    async fn download_subreddit_limited() {
+        let (client, _server) = super::super::mock::mock_client().await;
+
+        let listing = super::ListingOptions {
+            limit: Some(1),
+            ..Default::default()
+        };
+        let posts = super::download_subreddit(&client, "r/rexitTest".to_owned(), &listing, Default::default(), PathBuf::from("./out"), false).await.items;
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].title, "Second post");
+
+        // Only the post from 2023-05-06 is in range
+        let listing = super::ListingOptions {
+            since: Some(chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2023, 5, 1, 0, 0, 0).unwrap()),
+            ..Default::default()
+        };
+        let posts = super::download_subreddit(&client, "r/rexitTest".to_owned(), &listing, Default::default(), PathBuf::from("./out"), false).await.items;
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].title, "Testing Rexit");
+    }
+
+    #[tokio::test]
+    async fn download_subreddit_comments() {
+        let (client, _server) = super::super::mock::mock_client().await;
+
+        let options = super::PostOptions {
+            comments: Some(Default::default()),
+            ..Default::default()
+        };
+        let posts = super::download_subreddit(&client, "r/rexitTest".to_owned(), &Default::default(), options, PathBuf::from("./out"), false).await.items;
+
+        assert_eq!(posts[1].comments.len(), 2);
+        assert_eq!(posts[1].comments[0].replies[0].body, "Second");
+
+        // Without usernames no author is exported, replies included
+        let options = super::PostOptions {
+            no_usernames: true,
+            ..options
+        };
+        let posts = super::download_subreddit(&client, "r/rexitTest".to_owned(), &Default::default(), options, PathBuf::from("./out"), false).await.items;
+        assert_eq!(posts[1].author, "N/A");
+        assert_eq!(posts[1].comments[0].author, "N/A");
+        assert_eq!(posts[1].comments[0].replies[0].author, "N/A");
+    }
 }
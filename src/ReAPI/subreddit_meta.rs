@@ -0,0 +1,178 @@
+//! Downloads the description, sidebar, rules and wiki of a subreddit.
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::Client;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubredditMeta {
+    /// Example: r/redditdev
+    pub name: String,
+    pub title: String,
+    pub public_description: String,
+    /// The sidebar (Markdown)
+    pub description: String,
+    pub subscribers: u64,
+    /// Unix timestamp (UTC)
+    pub created: f64,
+    pub rules: Vec<Rule>,
+    pub wiki: Vec<WikiPage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rule {
+    pub short_name: String,
+    /// Markdown
+    #[serde(default)]
+    pub description: String,
+    /// What the rule applies to: link, comment or all
+    #[serde(default)]
+    pub kind: String,
+    #[serde(default)]
+    pub priority: u32,
+    /// Unix timestamp (UTC)
+    #[serde(default, alias = "created_utc")]
+    pub created: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WikiPage {
+    /// Path of the page (Example: config/sidebar)
+    pub name: String,
+    /// Markdown
+    pub content: String,
+    /// Unix timestamp (UTC) of the latest revision
+    pub revision_date: f64,
+    pub revision_by: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct About {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    public_description: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    subscribers: u64,
+    #[serde(default)]
+    created_utc: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Rules {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WikiPageData {
+    #[serde(default)]
+    content_md: String,
+    #[serde(default)]
+    revision_date: f64,
+    #[serde(default)]
+    revision_by: Value,
+}
+
+/// Downloads the about page, rules and every readable wiki page of `subreddit_name` (Example: r/redditdev)
+pub async fn download_meta(client: &Client, subreddit_name: &str) -> SubredditMeta {
+    info!("Getting subreddit about page, rules and wiki");
+    let base = format!("/{}", subreddit_name.trim_matches('/'));
+
+    let about: Option<About> = get(client, &format!("{base}/about.json"), "/data").await;
+    let about = about.unwrap_or_default();
+
+    let rules: Option<Rules> = get(client, &format!("{base}/about/rules.json"), "").await;
+
+    let mut wiki = vec![];
+    for page in wiki_pages(client, &base).await {
+        let data: Option<WikiPageData> = get(client, &format!("{base}/wiki/{page}.json"), "/data").await;
+        let Some(data) = data else {
+            continue;
+        };
+
+        wiki.push(WikiPage {
+            name: page,
+            content: data.content_md,
+            revision_date: data.revision_date,
+            revision_by: data.revision_by["data"]["name"].as_str().map(str::to_owned),
+        });
+    }
+    info!("Got {} wiki page(s)", wiki.len());
+
+    SubredditMeta {
+        name: subreddit_name.trim_matches('/').to_owned(),
+        title: about.title,
+        public_description: about.public_description,
+        description: about.description,
+        subscribers: about.subscribers,
+        created: about.created_utc,
+        rules: rules.map(|rules| rules.rules).unwrap_or_default(),
+        wiki,
+    }
+}
+
+/// Number of requests [download_meta] makes: the about page, the rules, the list of wiki pages and every page
+pub async fn meta_requests(client: &Client, subreddit_name: &str) -> usize {
+    let base = format!("/{}", subreddit_name.trim_matches('/'));
+
+    3 + wiki_pages(client, &base).await.len()
+}
+
+/// Paths of the wiki pages below `base` (Example: /r/redditdev); the wiki can be disabled or private
+async fn wiki_pages(client: &Client, base: &str) -> Vec<String> {
+    get(client, &format!("{base}/wiki/pages.json"), "/data").await.unwrap_or_default()
+}
+
+/// Requests a JSON document and parses the value at `pointer`; None if it is not available
+async fn get<T: DeserializeOwned>(client: &Client, path: &str, pointer: &str) -> Option<T> {
+    let response = match client.send(client.get(client.reddit(path))).await {
+        Ok(response) => response,
+        Err(error) => {
+            warn!("Could not get {path}: {error}");
+            return None;
+        }
+    };
+
+    if !response.status().is_success() {
+        warn!("Could not get {path}: {}", response.status());
+        return None;
+    }
+
+    let value: Value = serde_json::from_str(response.text().await.ok()?.as_str()).ok()?;
+    match serde_json::from_value(value.pointer(pointer)?.clone()) {
+        Ok(parsed) => Some(parsed),
+        Err(error) => {
+            warn!("Failed to parse {path}: {error}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[tokio::test]
+    async fn download_meta() {
+        let (client, _server) = super::super::mock::mock_client().await;
+
+        let meta = super::download_meta(&client, "r/rexitTest").await;
+
+        assert_eq!(meta.title, "Rexit Test");
+        assert_eq!(meta.description, "**Sidebar** text");
+        assert_eq!(meta.subscribers, 3);
+        assert_eq!(meta.rules.len(), 2);
+        assert_eq!(meta.rules[1].short_name, "No spam");
+
+        // The private page is skipped
+        assert_eq!(meta.wiki.len(), 1);
+        assert_eq!(meta.wiki[0].name, "index");
+        assert_eq!(meta.wiki[0].content, "# Welcome");
+        assert_eq!(meta.wiki[0].revision_by.as_deref(), Some("rexitTest"));
+
+        // The private page is requested too
+        assert_eq!(super::meta_requests(&client, "r/rexitTest").await, 5);
+    }
+}
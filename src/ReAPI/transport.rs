@@ -0,0 +1,17 @@
+//! The HTTP transport the [Client](super::Client) sends its requests through.
+use async_trait::async_trait;
+
+/// Executes a fully built request and returns the response.
+///
+/// The default transport is a [reqwest::Client]; swapping it out allows running the ReAPI against something that is not Reddit.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn execute(&self, request: reqwest::Request) -> reqwest::Result<reqwest::Response>;
+}
+
+#[async_trait]
+impl Transport for reqwest::Client {
+    async fn execute(&self, request: reqwest::Request) -> reqwest::Result<reqwest::Response> {
+        reqwest::Client::execute(self, request).await
+    }
+}
@@ -1,6 +1,6 @@
 use super::Client;
 use cached::SizedCache;
-use log::{debug, info};
+use log::info;
 
 #[derive(Clone, Debug)]
 pub struct User {
@@ -14,12 +14,10 @@ pub struct User {
     convert = r#"{ format!("{}", id) }"#
 )]
 pub async fn get_user(client: &Client, id: String, redact: bool) -> User {
-    let url = format!("https://matrix.redditspace.com/_matrix/client/r0/profile/{id}/displayname",);
+    let url = client.matrix(&format!("/_matrix/client/r0/profile/{id}/displayname"));
 
     let response = client
-        .reqwest_client
-        .get(url)
-        .send()
+        .send(client.get(url))
         .await
         .expect("Failed to send HTTP request");
 
@@ -35,21 +33,46 @@ pub async fn get_user(client: &Client, id: String, redact: bool) -> User {
     }
 
     User {
-        id: id,
+        id,
         displayname: value["displayname"].as_str().unwrap().to_string(),
     }
 }
 
+/// Returns the name of the logged in user
+pub async fn whoami(client: &Client, redact: bool) -> Result<String, String> {
+    let request = client
+        .get(client.matrix("/_matrix/client/v3/account/whoami"))
+        .header("Authorization", format!("Bearer {}", client.bearer_token()));
+    let response = client
+        .send(request)
+        .await
+        .map_err(|error| format!("Failed to send HTTP request: {error}"))?;
+
+    let text = response.text().await.map_err(|error| format!("Failed to read whoami response: {error}"))?;
+    let value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|_| "Error parsing whoami response; likely error with login")?;
+    let id = value["user_id"].as_str().ok_or("Error reading user id; likely error with login")?.to_string();
+
+    Ok(get_user(client, id, redact).await.displayname)
+}
+
 #[cfg(test)]
 mod tests {
 
     #[tokio::test]
     async fn get_user() {
-        let client = super::super::new_client(true);
+        let (client, _server) = super::super::mock::mock_client().await;
         let id = "@t2_9b09u6gps:reddit.com".to_string();
 
         let result = super::get_user(&client, id, false);
 
         assert_eq!(result.await.displayname, "rexitTest");
     }
+
+    #[tokio::test]
+    async fn whoami() {
+        let (client, _server) = super::super::mock::mock_client().await;
+
+        assert_eq!(super::whoami(&client, false).await.unwrap(), "rexitTest");
+    }
 }
@@ -2,7 +2,11 @@
 
 use std::path::PathBuf;
 
-pub use clap::{Parser, Subcommand};
+use chrono::{DateTime, NaiveDate, Utc};
+pub use clap::{Args, CommandFactory, Parser, Subcommand};
+
+use crate::export::Format;
+use crate::ReAPI::{SearchSort, Sort, TimeWindow, UserListing};
 
 /// CLI argument parser, see the Cli struct for the possible arguments
 #[derive(Parser, Debug)]
@@ -12,98 +16,407 @@ pub struct Cli {
     // Command Line Options structure
     #[command(subcommand)]
     pub command: Commands,
+
+    #[command(flatten)]
+    pub global: GlobalArgs,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
+    /// Export the chats
     Messages {
-        /// The formats to export to. Options: csv,json,txt
-        #[arg(short, long, default_value = "txt,json,csv")]
-        formats: String,
+        #[command(flatten)]
+        rooms: RoomArgs,
 
-        /// To use the bearer token flow, instead of username and password
-        #[arg(short, long)]
-        token: bool,
+        #[command(flatten)]
+        filter: MessageArgs,
+    },
+    /// Export the saved posts and comments
+    Saved,
+    /// Manage downloaded media
+    Media {
+        #[command(subcommand)]
+        command: MediaCommand,
+    },
+    /// Export the posts of subreddits
+    Subreddit {
+        /// Names of the subreddits (Example: r/redditdev r/rust+r/golang) or multireddits (Example: /user/x/m/name)
+        #[arg(required = true)]
+        names: Vec<String>,
 
-        /// Output images too (outputs to images folder)
-        #[arg(short, long)]
-        images: bool,
+        /// Order of the posts
+        #[arg(long, value_enum, default_value_t = Sort::Hot)]
+        sort: Sort,
 
-        /// What folder to output to
-        #[arg(short, long, default_value = "./out")]
-        out: PathBuf,
+        /// Also archive the about page, sidebar, rules and wiki (outputs next to the posts)
+        #[arg(long)]
+        meta: bool,
 
-        /// Trust proxy certificates
-        #[arg(short, long)]
-        debug: bool,
+        #[command(flatten)]
+        listing: ListingArgs,
+    },
+    /// Export the posts matching a search
+    Search {
+        /// What to search for
+        query: String,
 
-        /// Not Retrieve usernames (Is a lot faster)
+        /// Only search this subreddit (Example: r/redditdev)
         #[arg(long)]
-        no_usernames: bool,
+        subreddit: Option<String>,
 
-        /// Redact
-        #[arg(long)]
-        redact: bool
-    },
-    Saved {
-        /// The formats to export to. Options: csv,json,txt
-        #[arg(short, long, default_value = "txt,json,csv")]
-        formats: String,
+        /// Order of the results
+        #[arg(long, value_enum, default_value_t = SearchSort::Relevance)]
+        sort: SearchSort,
 
-        /// To use the bearer token flow, instead of username and password
-        #[arg(short, long)]
-        token: bool,
+        #[command(flatten)]
+        listing: ListingArgs,
+    },
+    /// Export the history of a user profile
+    User {
+        /// Name of the user (Example: u/spez); defaults to the logged in user
+        name: Option<String>,
 
-        /// Output images too (outputs to images folder)
-        #[arg(short, long)]
-        images: bool,
+        /// The listings to export [default: submitted,comments; all of them for the logged in user]
+        #[arg(long, value_enum, value_delimiter = ',')]
+        listings: Vec<UserListing>,
 
-        /// What folder to output to
-        #[arg(short, long, default_value = "./out")]
-        out: PathBuf,
+        /// Order of the submitted posts and comments
+        #[arg(long, value_enum, default_value_t = Sort::New)]
+        sort: Sort,
 
-        // Trust proxy certificates
-        #[arg(short, long)]
-        debug: bool,
+        #[command(flatten)]
+        listing: ListingArgs,
+    },
+    /// Export the legacy private messages (inbox and sent)
+    Inbox,
+    /// Export the chats, saved items, own profile history and subreddits with a single login
+    All {
+        /// Subreddits or multireddits to export as well (Example: r/redditdev,r/rust)
+        #[arg(long, value_delimiter = ',')]
+        subreddits: Vec<String>,
 
-        /// Not Retrieve usernames (Is a lot faster)
+        /// Also archive the about page, sidebar, rules and wiki of the subreddits
         #[arg(long)]
-        no_usernames: bool,
-      
-        /// Redact
+        meta: bool,
+
+        #[command(flatten)]
+        listing: ListingArgs,
+
+        #[command(flatten)]
+        rooms: RoomArgs,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MediaCommand {
+    /// Check that every downloaded media file exists, is complete and decodes as its type
+    Verify {
+        /// Download broken files again
         #[arg(long)]
-        redact: bool
+        repair: bool,
     },
-    Subreddit {
-        /// Name of the subreddit (Example: r/redditdev)
-        name: String,
+}
 
-        /// The formats to export to. Options: csv,json,txt
-        #[arg(short, long, default_value = "txt,json,csv")]
-        formats: String,
+impl Commands {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Commands::Messages { .. } => "messages",
+            Commands::Saved => "saved",
+            Commands::Media { .. } => "media",
+            Commands::Subreddit { .. } => "subreddit",
+            Commands::Search { .. } => "search",
+            Commands::User { .. } => "user",
+            Commands::Inbox => "inbox",
+            Commands::All { .. } => "all",
+        }
+    }
+}
 
-        /// To use the bearer token flow, instead of username and password
-        #[arg(short, long)]
-        token: bool,
+/// Options shared by all subcommands; they can be given before or after the subcommand
+#[derive(Args, Debug, Clone)]
+pub struct GlobalArgs {
+    /// The formats to export to [default: txt,json,csv]
+    #[arg(short, long, global = true, value_enum, value_delimiter = ',')]
+    pub formats: Vec<Format>,
 
-        /// Output images too (outputs to images folder)
-        #[arg(short, long)]
-        images: bool,
+    /// To use the bearer token flow, instead of username and password
+    #[arg(short, long, global = true)]
+    pub token: bool,
 
-        /// What folder to output to
-        #[arg(short, long, default_value = "./out")]
-        out: PathBuf,
+    /// Output images too (outputs to images folder)
+    #[arg(short, long, global = true)]
+    pub images: bool,
 
-        // Trust proxy certificates
-        #[arg(short, long)]
-        debug: bool,
+    /// Merge Reddit videos with their audio track (requires ffmpeg)
+    #[arg(long, global = true)]
+    pub mux_audio: bool,
 
-        /// Not Retrieve usernames (Is a lot faster)
-        #[arg(long)]
-        no_usernames: bool,
+    /// What folder to output to
+    #[arg(short, long, global = true, default_value = "./out")]
+    pub out: PathBuf,
 
-        /// Redact
-        #[arg(long)]
-        redact: bool
-    },
+    /// Replace usernames with N/A (chats do not look them up, which is a lot faster)
+    #[arg(long, global = true)]
+    pub no_usernames: bool,
+
+    /// Redact
+    #[arg(long, global = true)]
+    pub redact: bool,
+
+    /// Only print what would be exported (rooms, items, media); downloads no media and writes nothing to disk
+    #[arg(long, global = true, conflicts_with = "record")]
+    pub dry_run: bool,
+
+    /// Print the plan of --dry-run as JSON
+    #[arg(long, global = true, requires = "dry_run")]
+    pub json: bool,
+
+    #[command(flatten)]
+    pub network: NetworkArgs,
+
+    #[command(flatten)]
+    pub media: MediaArgs,
+
+    #[command(flatten)]
+    pub comments: CommentArgs,
+}
+
+/// Subcommands exporting posts
+const POSTS: [&str; 5] = ["saved", "subreddit", "search", "user", "all"];
+/// Subcommands downloading media
+const MEDIA: [&str; 6] = ["messages", "saved", "subreddit", "search", "user", "all"];
+/// Subcommands writing exports
+const EXPORTS: [&str; 7] = ["messages", "saved", "subreddit", "search", "user", "inbox", "all"];
+/// Subcommands that log in
+const LOGIN: [&str; 5] = ["messages", "saved", "user", "inbox", "all"];
+
+impl GlobalArgs {
+    /// The formats to export to, all of them by default
+    pub fn formats(&self) -> Vec<Format> {
+        if self.formats.is_empty() {
+            vec![Format::Txt, Format::Json, Format::Csv]
+        } else {
+            self.formats.clone()
+        }
+    }
+}
+
+impl Cli {
+    /// Checks that every given option applies to the subcommand
+    pub fn validate(&self) -> Result<(), String> {
+        let global = &self.global;
+        let command = self.command.name();
+
+        let options: [(&str, bool, &[&str]); 9] = [
+            ("--formats", !global.formats.is_empty(), &EXPORTS),
+            ("--token", global.token, &LOGIN),
+            ("--images", global.images, &MEDIA),
+            ("--mux-audio", global.mux_audio, &POSTS),
+            ("--no-usernames", global.no_usernames, &EXPORTS),
+            ("--strip-metadata", global.media.strip_metadata, &MEDIA),
+            ("--thumbnails", global.media.thumbnails, &MEDIA),
+            ("--comments", global.comments.comments, &POSTS),
+            ("--dry-run", global.dry_run, &EXPORTS),
+        ];
+
+        for (option, given, commands) in options {
+            if given && !commands.contains(&command) {
+                return Err(format!("{option} can not be used with `rexit {command}`"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Options for the HTTP client
+#[derive(Args, Debug, Clone)]
+pub struct NetworkArgs {
+    /// Trust proxy certificates
+    #[arg(short, long, global = true)]
+    pub debug: bool,
+
+    /// Send all requests through this proxy (Example: http://127.0.0.1:8080, socks5://127.0.0.1:9050)
+    #[arg(long, global = true)]
+    pub proxy: Option<String>,
+
+    /// Additionally trust the certificates in this PEM file (e.g. of an inspecting proxy)
+    #[arg(long, global = true)]
+    pub ca_cert: Option<PathBuf>,
+
+    /// User agent to send instead of the default desktop browser one
+    #[arg(long, global = true)]
+    pub user_agent: Option<String>,
+
+    /// Request timeout in seconds
+    #[arg(long, global = true, default_value_t = 60)]
+    pub timeout: u64,
+
+    /// Save every HTTP request and response to this folder (without auth headers; --redact also scrubs usernames)
+    #[arg(long, global = true, conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Run purely from the recordings in this folder instead of contacting Reddit
+    #[arg(long, global = true)]
+    pub replay: Option<PathBuf>,
+}
+
+/// Which chats to export
+#[derive(Args, Debug, Clone)]
+pub struct RoomArgs {
+    /// Only export this room, by id or name (can be repeated)
+    #[arg(long = "room")]
+    pub rooms: Vec<String>,
+
+    /// Skip this room, by id or name (can be repeated)
+    #[arg(long = "exclude-room")]
+    pub exclude: Vec<String>,
+
+    /// Only export rooms with this member (can be repeated; Example: u/spez)
+    #[arg(long = "member")]
+    pub members: Vec<String>,
+
+    /// Pick the rooms to export from a list (with names and last activity)
+    #[arg(long)]
+    pub interactive: bool,
+}
+
+/// Which messages of the chats to export
+#[derive(Args, Debug, Clone)]
+pub struct MessageArgs {
+    /// Only messages sent at or after this date (Example: 2023-03-01 or 2023-03-01T12:00:00Z)
+    #[arg(long, value_parser = parse_date)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Only messages sent before this date (Example: 2023-07-01)
+    #[arg(long, value_parser = parse_date)]
+    pub until: Option<DateTime<Utc>>,
+
+    /// Only messages of this user (can be repeated; Example: u/spez)
+    #[arg(long = "from-user")]
+    pub from_users: Vec<String>,
+}
+
+/// Which posts of a listing to download
+#[derive(Args, Debug, Clone)]
+pub struct ListingArgs {
+    /// Time window of the top and controversial sorts (and of searches)
+    #[arg(long, value_enum)]
+    pub time: Option<TimeWindow>,
+
+    /// Maximum number of posts to download
+    #[arg(long)]
+    pub limit: Option<usize>,
+
+    /// Only posts created at or after this date (Example: 2023-05-01 or 2023-05-01T12:00:00Z)
+    #[arg(long, value_parser = parse_date)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Only posts created before this date (Example: 2023-05-08)
+    #[arg(long, value_parser = parse_date)]
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Parses a date (midnight UTC) or an RFC 3339 timestamp
+pub fn parse_date(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    }
+
+    DateTime::parse_from_rfc3339(value)
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(|_| format!("invalid date `{value}`, expected YYYY-MM-DD or RFC 3339"))
+}
+
+/// Downloading the discussion of posts
+#[derive(Args, Debug, Clone)]
+pub struct CommentArgs {
+    /// Export the comments of each post too
+    #[arg(long, global = true)]
+    pub comments: bool,
+
+    /// Deepest reply level to export (0 only exports top level comments)
+    #[arg(long, global = true, requires = "comments")]
+    pub comment_depth: Option<u32>,
+
+    /// Maximum number of comments to export per post
+    #[arg(long, global = true, requires = "comments")]
+    pub comment_limit: Option<usize>,
+}
+
+/// Processing of downloaded media
+#[derive(Args, Debug, Clone)]
+pub struct MediaArgs {
+    /// Remove EXIF (including GPS) and other metadata from downloaded JPEG, PNG and WebP images
+    #[arg(long, global = true)]
+    pub strip_metadata: bool,
+
+    /// Generate small thumbnails of downloaded images (outputs to thumbnails folder)
+    #[arg(long, global = true)]
+    pub thumbnails: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{Cli, Parser};
+    use crate::export::Format;
+
+    #[test]
+    fn global_options() {
+        // Global options are accepted before and after the subcommand
+        let cli = Cli::try_parse_from(["rexit", "-f", "json,txt", "messages", "--images", "--no-usernames"]).unwrap();
+        assert_eq!(cli.global.formats(), vec![Format::Json, Format::Txt]);
+        assert!(cli.global.images);
+        assert!(cli.validate().is_ok());
+
+        let cli = Cli::try_parse_from(["rexit", "messages", "--room", "Mock chat", "--exclude-room", "!a:reddit.com", "--member", "u/spez"]).unwrap();
+        let super::Commands::Messages { rooms, .. } = cli.command else {
+            panic!("Expected the messages command");
+        };
+        assert_eq!(rooms.rooms, vec!["Mock chat"]);
+        assert_eq!(rooms.exclude, vec!["!a:reddit.com"]);
+        assert_eq!(rooms.members, vec!["u/spez"]);
+
+        let cli = Cli::try_parse_from(["rexit", "saved"]).unwrap();
+        assert_eq!(cli.global.formats(), vec![Format::Txt, Format::Json, Format::Csv]);
+
+        assert!(Cli::try_parse_from(["rexit", "saved", "--formats", "pdf"]).is_err());
+    }
+
+    #[test]
+    fn validate() {
+        let cli = Cli::try_parse_from(["rexit", "saved", "--no-usernames"]).unwrap();
+        assert!(cli.validate().is_ok());
+
+        let cli = Cli::try_parse_from(["rexit", "media", "verify", "--no-usernames"]).unwrap();
+        assert_eq!(cli.validate(), Err("--no-usernames can not be used with `rexit media`".to_owned()));
+
+        let cli = Cli::try_parse_from(["rexit", "subreddit", "r/rexitTest", "--comments", "--mux-audio"]).unwrap();
+        assert!(cli.validate().is_ok());
+
+        let cli = Cli::try_parse_from(["rexit", "all", "--subreddits", "r/a,r/b", "--no-usernames", "--comments"]).unwrap();
+        assert!(cli.validate().is_ok());
+
+        let cli = Cli::try_parse_from(["rexit", "media", "verify", "--images"]).unwrap();
+        assert!(cli.validate().is_err());
+
+        let cli = Cli::try_parse_from(["rexit", "media", "verify", "--dry-run"]).unwrap();
+        assert!(cli.validate().is_err());
+
+        // --json only changes how the plan is printed
+        assert!(Cli::try_parse_from(["rexit", "saved", "--json"]).is_err());
+        assert!(Cli::try_parse_from(["rexit", "saved", "--dry-run", "--json"]).is_ok());
+    }
+
+    #[test]
+    fn parse_date() {
+        assert_eq!(super::parse_date("2023-05-01"), Ok(Utc.with_ymd_and_hms(2023, 5, 1, 0, 0, 0).unwrap()));
+        assert_eq!(
+            super::parse_date("2023-05-01T12:00:00+02:00"),
+            Ok(Utc.with_ymd_and_hms(2023, 5, 1, 10, 0, 0).unwrap())
+        );
+        assert!(super::parse_date("yesterday").is_err());
+    }
 }
@@ -2,35 +2,73 @@ use std::fs::{self, OpenOptions};
 use std::io::Write;
 use std::path::Path;
 
+use clap::ValueEnum;
+use log::warn;
+
 use crate::ReAPI;
 
-/// Export the chats into a .txt file
-pub fn export_room_chats_txt(room: ReAPI::Room, out_folder: &Path) {
-    let mut output_buffer: String = String::new();
-    let path = out_folder.join(format!("messages/{}.txt", &room.id[1..10]));
+/// The formats exports are written in
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    Txt,
+    Json,
+    Csv,
+}
 
-    for message in room.messages() {
-        if let ReAPI::Content::Message(text) = message.content {
-            let line: String = format!(
-                "[{}] {}: {}\n",
-                message
-                    .timestamp
-                    .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
-                    .to_string(),
-                message.author,
-                text
-            );
+/// Name of the export files of a room: the start of Matrix room ids, private message conversations by their id
+fn room_file_name(id: &str) -> String {
+    match id.strip_prefix('!') {
+        Some(id) => id.chars().take(9).collect(),
+        None => id.to_owned(),
+    }
+}
 
-            output_buffer.push_str(line.as_str());
+/// Export each room into `folder` in all of the formats
+pub fn export_rooms(rooms: Vec<ReAPI::Room>, formats: &[Format], folder: &Path) {
+    for room in rooms {
+        for format in formats {
+            match format {
+                Format::Txt => export_room_chats_txt(room.to_owned(), folder),
+                Format::Json => export_room_chats_json(room.to_owned(), folder),
+                Format::Csv => export_room_chats_csv(room.to_owned(), folder),
+            }
         }
     }
+}
+
+/// Export the chats into a .txt file in `folder` (e.g. out/messages)
+pub fn export_room_chats_txt(room: ReAPI::Room, folder: &Path) {
+    let mut output_buffer: String = String::new();
+    let path = folder.join(format!("{}.txt", room_file_name(&room.id)));
+
+    if let Some(name) = &room.name {
+        output_buffer.push_str(format!("Subject: {name}\n").as_str());
+    }
+
+    for message in room.messages() {
+        let text = match message.content {
+            ReAPI::Content::Message(text) => text,
+            // Prefer the local file over the mxc:// URL
+            ReAPI::Content::Media(media) => format!("FILE {}", media.path.unwrap_or(media.mxc)),
+        };
+        let line: String = format!(
+            "[{}] {}: {}\n",
+            message
+                .timestamp
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            message.author,
+            text
+        );
+
+        output_buffer.push_str(line.as_str());
+    }
 
     std::fs::write(path, output_buffer).unwrap();
 }
 
 /// Export the chats into .json files.
-pub fn export_room_chats_json(room: ReAPI::Room, out_folder: &Path) {
-    let path = out_folder.join(format!("messages/{}.json", &room.id[1..10]));
+pub fn export_room_chats_json(room: ReAPI::Room, folder: &Path) {
+    let path = folder.join(format!("{}.json", room_file_name(&room.id)));
 
     let file_data = serde_json::to_string(&room).unwrap();
 
@@ -38,31 +76,33 @@ pub fn export_room_chats_json(room: ReAPI::Room, out_folder: &Path) {
 }
 
 /// Export chats into csv
-pub fn export_room_chats_csv(room: ReAPI::Room, out_folder: &Path) {
+pub fn export_room_chats_csv(room: ReAPI::Room, folder: &Path) {
     // Create the file for each chat / room
-    let path = out_folder.join(format!("messages/{}.csv", &room.id[1..10]));
+    let path = folder.join(format!("{}.csv", room_file_name(&room.id)));
 
-    std::fs::write(path.clone(), "timestamp, author, message \n").unwrap();
+    std::fs::write(path.clone(), "timestamp, author, message, file \n").unwrap();
 
     // Iterate over each message in the chat; append to the file
     for message in room.messages() {
         // Format for the line to be appended
-        let mut line: String = String::new();
-
-        if let ReAPI::Content::Message(text) = message.content {
-            line = format!(
-                "{}, {}, {},",
-                message
-                    .timestamp
-                    .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
-                    .to_string(),
-                message.author,
-                text
-            );
-        }
+        let (text, file) = match message.content {
+            ReAPI::Content::Message(text) => (text, String::new()),
+            ReAPI::Content::Media(media) => (
+                media.filename.unwrap_or_default(),
+                media.path.unwrap_or(media.mxc),
+            ),
+        };
+        let line: String = format!(
+            "{}, {}, {}, {}",
+            message
+                .timestamp
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            message.author,
+            text,
+            file
+        );
 
         let mut file = OpenOptions::new()
-            .write(true)
             .append(true)
             .open(path.clone())
             .unwrap();
@@ -73,15 +113,26 @@ pub fn export_room_chats_csv(room: ReAPI::Room, out_folder: &Path) {
     }
 }
 
-/// Export saved posts
+/// Export saved posts and comments
 pub fn export_saved_posts(
-    post_array: Vec<ReAPI::saved_posts::SavedPost>,
-    formats: Vec<&str>,
+    post_array: Vec<ReAPI::SavedItem>,
+    formats: &[Format],
     out_folder: &Path,
+) {
+    export_items(post_array, formats, out_folder, "saved_posts", "saved_posts");
+}
+
+/// Export posts and comments to `<folder>/<name>.<format>` (e.g. the history of a user)
+pub fn export_items(
+    post_array: Vec<ReAPI::SavedItem>,
+    formats: &[Format],
+    out_folder: &Path,
+    folder: &str,
+    name: &str,
 ) {
     // Export to JSON
-    if formats.contains(&"json") {
-        let path = out_folder.join("saved_posts/saved_posts.json");
+    if formats.contains(&Format::Json) {
+        let path = out_folder.join(format!("{folder}/{name}.json"));
 
         let file_data = serde_json::to_string(&post_array).unwrap();
 
@@ -89,33 +140,53 @@ pub fn export_saved_posts(
     }
 
     // Export to txt
-    if formats.contains(&"txt") {
-        let path = out_folder.join("saved_posts/saved_posts.txt");
+    if formats.contains(&Format::Txt) {
+        let path = out_folder.join(format!("{folder}/{name}.txt"));
         let mut output_buffer: String = String::new();
 
-        for post in &post_array {
+        for item in &post_array {
             // Iterate over each line and append to .txt file
-            let line: String = format!(
-                "Title: {}, Subreddit: {}, Body: {} Permalink: {}, Images {:?}\n",
-                post.title, post.subreddit_name, post.body_text, post.permalink, post.img_url
-            );
-
-            output_buffer.push_str(line.as_str());
+            match item {
+                ReAPI::SavedItem::Post(post) => {
+                    output_buffer.push_str(post_txt(post).as_str());
+                    export_comments_txt(&post.comments, &mut output_buffer);
+                }
+                ReAPI::SavedItem::Comment(comment) => {
+                    let line: String = format!(
+                        "Comment by {} on: {}, Subreddit: {}, Body: {}, Score: {}, Permalink: {}\n",
+                        comment.author, comment.link_title, comment.subreddit_name, comment.body, comment.score, comment.permalink
+                    );
+
+                    output_buffer.push_str(line.as_str());
+                }
+            }
         }
         std::fs::write(path, output_buffer).unwrap();
     }
 
-    if formats.contains(&"csv") {
+    if formats.contains(&Format::Csv) {
         // Export to CSV
-        let path = out_folder.join("saved_posts/saved_posts.csv");
-        let mut output_buffer: String = "Title, Subreddit, Body, Permalink, Images\n".to_owned();
+        let path = out_folder.join(format!("{folder}/{name}.csv"));
+        let mut output_buffer: String = format!("{POST_CSV_HEADER}, Kind\n");
 
-        for post in post_array {
+        for item in post_array {
             // Iterate over each line and append to .txt file
-            let line: String = format!(
-                "{}, {}, {}, {}, {:?}\n",
-                post.title, post.subreddit_name, post.body_text, post.permalink, post.img_url
-            );
+            // Comments are listed with the title of the post they were made on
+            let line: String = match item {
+                ReAPI::SavedItem::Post(post) => format!("{}, post\n", post_csv(&post)),
+                ReAPI::SavedItem::Comment(comment) => format!(
+                    "{}, {}, {}, {}, [], {}, {}, , , {}, , , , {}, , {}, comment\n",
+                    comment.link_title,
+                    comment.subreddit_name,
+                    comment.body,
+                    comment.permalink,
+                    comment.author,
+                    comment.score,
+                    timestamp(comment.created),
+                    comment.link_permalink,
+                    comment.id
+                ),
+            };
 
             output_buffer.push_str(line.as_str());
         }
@@ -123,15 +194,27 @@ pub fn export_saved_posts(
     }
 }
 
-/// Export subreddit
+/// Export subreddit to `subreddit/<folder_name>/<folder_name>.<format>`
 pub fn export_subreddit(
-    post_array: Vec<ReAPI::subreddit::Post>,
-    formats: Vec<&str>,
+    post_array: Vec<ReAPI::Post>,
+    formats: &[Format],
     out_folder: &Path,
+    folder_name: &str,
+) {
+    export_posts(post_array, formats, out_folder, &format!("subreddit/{folder_name}"), folder_name);
+}
+
+/// Export posts to `<folder>/<name>.<format>` (e.g. search results)
+pub fn export_posts(
+    post_array: Vec<ReAPI::Post>,
+    formats: &[Format],
+    out_folder: &Path,
+    folder: &str,
+    name: &str,
 ) {
     // Export to JSON
-    if formats.contains(&"json") {
-        let path = out_folder.join("subreddit/subreddit.json");
+    if formats.contains(&Format::Json) {
+        let path = out_folder.join(format!("{folder}/{name}.json"));
 
         let file_data = serde_json::to_string(&post_array).unwrap();
 
@@ -139,46 +222,152 @@ pub fn export_subreddit(
     }
 
     // Export to txt
-    if formats.contains(&"txt") {
-        let path = out_folder.join("subreddit/subreddit.txt");
+    if formats.contains(&Format::Txt) {
+        let path = out_folder.join(format!("{folder}/{name}.txt"));
         let mut output_buffer: String = String::new();
 
         for post in &post_array {
             // Iterate over each line and append to .txt file
-            let line: String = format!(
-                "Title: {}, Subreddit: {}, Body: {}, Permalink: {}, Images {:?}\n",
-                post.title, post.subreddit_name, post.body_text, post.permalink, post.img_url
-            );
-
-            output_buffer.push_str(line.as_str());
+            output_buffer.push_str(post_txt(post).as_str());
+            export_comments_txt(&post.comments, &mut output_buffer);
         }
         std::fs::write(path, output_buffer).unwrap();
     }
 
-    if formats.contains(&"csv") {
+    if formats.contains(&Format::Csv) {
         // Export to CSV
-        let path = out_folder.join("subreddit/subreddit.csv");
-        let mut output_buffer: String = "Title, Subreddit, Permalink, Images\n".to_owned();
+        let path = out_folder.join(format!("{folder}/{name}.csv"));
+        let mut output_buffer: String = format!("{POST_CSV_HEADER}\n");
 
         for post in post_array {
             // Iterate over each line and append to .txt file
-            let line: String = format!(
-                "{}, {}, {}, {}, {:?}\n",
-                post.title, post.subreddit_name, post.body_text, post.permalink, post.img_url
-            );
-
-            output_buffer.push_str(line.as_str());
+            output_buffer.push_str(format!("{}\n", post_csv(&post)).as_str());
         }
         std::fs::write(path, output_buffer).unwrap();
     }
 }
 
+/// Export the about page, rules and wiki of a subreddit as Markdown (`about.md`, `wiki/<page>.md`) and JSON (`about.json`) into `folder`
+pub fn export_subreddit_meta(meta: &ReAPI::SubredditMeta, folder: &Path) {
+    let path = folder.join("about.json");
+    let file_data = serde_json::to_string(meta).unwrap();
+    fs::write(path, file_data).expect("Unable to write file");
+
+    let mut output_buffer = format!(
+        "# {}\n\n{}, {} subscribers, created {}\n\n{}\n\n## Sidebar\n\n{}\n\n## Rules\n\n",
+        meta.title,
+        meta.name,
+        meta.subscribers,
+        timestamp(meta.created),
+        meta.public_description,
+        meta.description
+    );
+    for (number, rule) in meta.rules.iter().enumerate() {
+        output_buffer.push_str(format!("{}. **{}** ({})\n", number + 1, rule.short_name, rule.kind).as_str());
+        if !rule.description.is_empty() {
+            output_buffer.push_str(format!("\n   {}\n", rule.description.replace('\n', "\n   ")).as_str());
+        }
+    }
+    fs::write(folder.join("about.md"), output_buffer).expect("Unable to write file");
+
+    for page in &meta.wiki {
+        // Page names are paths like config/sidebar; backslashes and drive letters would be separators on Windows
+        if page.name.contains(['\\', ':']) || page.name.split('/').any(|part| part.is_empty() || part == "..") {
+            warn!("Skipping wiki page with invalid name: {}", page.name);
+            continue;
+        }
+        let path = folder.join("wiki").join(format!("{}.md", page.name));
+        fs::create_dir_all(path.parent().unwrap()).expect("Unable to create wiki folder");
+
+        let output_buffer = format!(
+            "<!-- Revision of {} by {} -->\n\n{}\n",
+            timestamp(page.revision_date),
+            page.revision_by.as_deref().unwrap_or("[unknown]"),
+            page.content
+        );
+        fs::write(path, output_buffer).expect("Unable to write file");
+    }
+}
+
+/// Columns of posts in CSV exports
+const POST_CSV_HEADER: &str = "Title, Subreddit, Body, Permalink, Images, Author, Score, Upvote Ratio, Comments, Created, Flair, NSFW, Spoiler, URL, Domain, Id";
+
+/// A post as a line of text
+fn post_txt(post: &ReAPI::Post) -> String {
+    format!(
+        "Title: {}, Subreddit: {}, Body: {}, Permalink: {}, Images {:?}, Author: {}, Score: {} ({}% upvoted), Comments: {}, Created: {}, Flair: {}, NSFW: {}, Spoiler: {}, URL: {}, Domain: {}, Id: {}\n",
+        post.title,
+        post.subreddit_name,
+        post.body_text,
+        post.permalink,
+        post.img_url,
+        post.author,
+        post.score,
+        (post.upvote_ratio * 100.0).round(),
+        post.num_comments,
+        timestamp(post.created),
+        post.flair.as_deref().unwrap_or_default(),
+        post.nsfw,
+        post.spoiler,
+        post.url.as_deref().unwrap_or_default(),
+        post.domain,
+        post.id
+    )
+}
+
+/// A post as a CSV row (without line break), see [POST_CSV_HEADER]
+fn post_csv(post: &ReAPI::Post) -> String {
+    format!(
+        "{}, {}, {}, {}, {:?}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}",
+        post.title,
+        post.subreddit_name,
+        post.body_text,
+        post.permalink,
+        post.img_url,
+        post.author,
+        post.score,
+        post.upvote_ratio,
+        post.num_comments,
+        timestamp(post.created),
+        post.flair.as_deref().unwrap_or_default(),
+        post.nsfw,
+        post.spoiler,
+        post.url.as_deref().unwrap_or_default(),
+        post.domain,
+        post.id
+    )
+}
+
+/// Formats a Unix timestamp like the message timestamps
+fn timestamp(created: f64) -> String {
+    chrono::DateTime::from_timestamp(created as i64, 0)
+        .unwrap_or_default()
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// Appends the comment threads below a post, indented by reply level
+fn export_comments_txt(comments: &[ReAPI::Comment], output_buffer: &mut String) {
+    for comment in comments {
+        let line: String = format!(
+            "{}- {} ({} points): {}\n",
+            "    ".repeat(comment.depth as usize + 1),
+            comment.author,
+            comment.score,
+            comment.body.replace('\n', " ")
+        );
+
+        output_buffer.push_str(line.as_str());
+        export_comments_txt(&comment.replies, output_buffer);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::{Path, PathBuf};
 
     use chrono::Utc;
 
+    use super::Format;
     use crate::ReAPI;
 
     // return a path from an env var with suffix or use tempdir
@@ -207,13 +396,121 @@ mod tests {
 
         let room = ReAPI::Room {
             id: "!fTxOL9GzJaZR71aLRSYstHNVR5j_Zi82L4hIVyjdHuw:reddit.com".to_owned(),
+            name: None,
             messages: messages_array,
         };
 
         // Export it
-        super::export_room_chats_csv(room.to_owned(), out_path);
-        super::export_room_chats_txt(room.to_owned(), out_path);
-        super::export_room_chats_json(room.to_owned(), out_path);
+        super::export_room_chats_csv(room.to_owned(), &out_path.join("messages"));
+        super::export_room_chats_txt(room.to_owned(), &out_path.join("messages"));
+        super::export_room_chats_json(room.to_owned(), &out_path.join("messages"));
+    }
+
+    #[test]
+    fn export_room_media() {
+        let out_dir = out_dir("export_room_media");
+        let out_path = out_dir.as_ref().as_ref();
+
+        std::fs::create_dir_all(out_path.join("messages/images")).unwrap();
+
+        let message = ReAPI::Message {
+            author: "rexitTest".to_owned(),
+            timestamp: Utc::now(),
+            content: ReAPI::Content::Media(ReAPI::messages::Media {
+                filename: Some("image.png".to_owned()),
+                mimetype: Some("image/png".to_owned()),
+                size: Some(67),
+                width: Some(1),
+                height: Some(1),
+                mxc: "mxc://reddit.com/mockimage0001".to_owned(),
+                path: Some("images/mockimage0001.png".to_owned()),
+                thumbnail: None,
+            }),
+        };
+
+        let room = ReAPI::Room {
+            id: "!mockroom0001:reddit.com".to_owned(),
+            name: None,
+            messages: Some(vec![message]),
+        };
+
+        super::export_room_chats_csv(room.to_owned(), &out_path.join("messages"));
+        super::export_room_chats_txt(room.to_owned(), &out_path.join("messages"));
+        super::export_room_chats_json(room.to_owned(), &out_path.join("messages"));
+
+        let txt = std::fs::read_to_string(out_path.join("messages/mockroom0.txt")).unwrap();
+        assert!(txt.ends_with("rexitTest: FILE images/mockimage0001.png\n"));
+
+        let csv = std::fs::read_to_string(out_path.join("messages/mockroom0.csv")).unwrap();
+        assert!(csv.contains(", rexitTest, image.png, images/mockimage0001.png"));
+
+        let json = std::fs::read_to_string(out_path.join("messages/mockroom0.json")).unwrap();
+        assert!(json.contains(r#""path":"images/mockimage0001.png""#));
+    }
+
+    #[test]
+    fn export_conversation() {
+        let out_dir = out_dir("export_conversation");
+        let out_path = out_dir.as_ref().as_ref();
+
+        std::fs::create_dir_all(out_path.join("inbox")).unwrap();
+
+        let room = ReAPI::Room {
+            id: "t4_1aaaaa".to_owned(),
+            name: Some("Hello".to_owned()),
+            messages: Some(vec![ReAPI::Message {
+                author: "otherUser".to_owned(),
+                timestamp: Utc::now(),
+                content: ReAPI::Content::Message("Hi there".to_owned()),
+            }]),
+        };
+
+        super::export_rooms(vec![room], &[Format::Txt, Format::Json, Format::Csv], &out_path.join("inbox"));
+
+        // Conversations are named by their full id and keep their subject
+        let txt = std::fs::read_to_string(out_path.join("inbox/t4_1aaaaa.txt")).unwrap();
+        assert!(txt.starts_with("Subject: Hello\n["));
+        assert!(txt.ends_with("otherUser: Hi there\n"));
+
+        let json = std::fs::read_to_string(out_path.join("inbox/t4_1aaaaa.json")).unwrap();
+        assert!(json.contains(r#""name":"Hello""#));
+        assert!(out_path.join("inbox/t4_1aaaaa.csv").exists());
+    }
+
+    #[test]
+    fn export_subreddit_meta() {
+        let out_dir = out_dir("export_subreddit_meta");
+        let out_path = out_dir.as_ref().as_ref();
+
+        std::fs::create_dir_all(out_path.join("subreddit")).unwrap();
+
+        let meta: ReAPI::SubredditMeta = serde_json::from_value(serde_json::json!({
+            "name": "r/rexitTest",
+            "title": "Rexit Test",
+            "public_description": "Testing Rexit",
+            "description": "**Sidebar** text",
+            "subscribers": 3,
+            "created": 1683400000.0,
+            "rules": [{ "short_name": "Be nice", "description": "Be nice\nto each other", "kind": "all", "priority": 0, "created": 0.0 }],
+            "wiki": [
+                { "name": "config/sidebar", "content": "# Sidebar", "revision_date": 1683401000.0, "revision_by": "rexitTest" },
+                { "name": "../escape", "content": "", "revision_date": 0.0, "revision_by": null },
+                { "name": "..\\..\\escape", "content": "", "revision_date": 0.0, "revision_by": null }
+            ]
+        }))
+        .unwrap();
+
+        super::export_subreddit_meta(&meta, &out_path.join("subreddit"));
+
+        let about = std::fs::read_to_string(out_path.join("subreddit/about.md")).unwrap();
+        assert!(about.starts_with("# Rexit Test\n\nr/rexitTest, 3 subscribers, created 2023-05-06T19:06:40Z\n"));
+        assert!(about.ends_with("1. **Be nice** (all)\n\n   Be nice\n   to each other\n"));
+
+        let page = std::fs::read_to_string(out_path.join("subreddit/wiki/config/sidebar.md")).unwrap();
+        assert_eq!(page, "<!-- Revision of 2023-05-06T19:23:20Z by rexitTest -->\n\n# Sidebar\n");
+        assert!(!out_path.join("escape.md").exists());
+        assert!(!out_path.join("subreddit/wiki/..\\..\\escape.md").exists());
+        assert!(out_path.join("subreddit/about.json").exists());
     }
 
     #[test]
@@ -227,17 +524,76 @@ mod tests {
 
         std::fs::create_dir_all(out_path.join("saved_posts")).unwrap();
 
-        let mut posts: Vec<ReAPI::SavedPost> = Vec::new();
+        let mut posts: Vec<ReAPI::SavedItem> = Vec::new();
 
-        let post = ReAPI::SavedPost {
+        let post = ReAPI::Post {
+            id: "t3_134bv4v".to_owned(),
             body_text: "hihi".to_owned(),
             title: "Da fehlt doch was".to_owned(),
+            author: "rexitTest".to_owned(),
             subreddit_name: "r/hamburg".to_owned(),
             permalink: "/r/hamburg/comments/134bv4v/da_fehlt_doch_was/".to_owned(),
+            url: None,
+            domain: "self.hamburg".to_owned(),
             img_url: ["https://preview.redd.it/…051acd31351105e323c5d7a6".to_owned()].to_vec(),
+            score: 42,
+            upvote_ratio: 0.97,
+            num_comments: 2,
+            created: 1683402799.0,
+            flair: Some("Frage".to_owned()),
+            nsfw: false,
+            spoiler: false,
+            comments: vec![ReAPI::Comment {
+                id: "jj1aaaa".to_owned(),
+                author: "rexitTest".to_owned(),
+                body: "First!".to_owned(),
+                score: 5,
+                created: 1683402799.0,
+                depth: 0,
+                replies: vec![ReAPI::Comment {
+                    id: "jj1bbbb".to_owned(),
+                    author: "otherUser".to_owned(),
+                    body: "Second\nline".to_owned(),
+                    score: 2,
+                    created: 1683402800.0,
+                    depth: 1,
+                    replies: vec![],
+                }],
+            }],
         };
-        posts.push(post);
+        posts.push(ReAPI::SavedItem::Post(post));
 
-        super::export_saved_posts(posts, ["txt", "json", "csv"].to_vec(), out_path)
+        let comment = ReAPI::saved_posts::SavedComment {
+            id: "t1_jj1aaaa".to_owned(),
+            author: "rexitTest".to_owned(),
+            body: "Saved comment".to_owned(),
+            score: 3,
+            created: 1683402799.0,
+            subreddit_name: "r/rexitTest".to_owned(),
+            link_title: "Testing Rexit".to_owned(),
+            link_permalink: "https://www.reddit.com/r/rexitTest/comments/13a9rn6/testing_rexit/".to_owned(),
+            permalink: "/r/rexitTest/comments/13a9rn6/testing_rexit/jj1aaaa/".to_owned(),
+        };
+        posts.push(ReAPI::SavedItem::Comment(comment));
+
+        super::export_saved_posts(posts, &[Format::Txt, Format::Json, Format::Csv], out_path);
+
+        // Replies are indented below their parent
+        let txt = std::fs::read_to_string(out_path.join("saved_posts/saved_posts.txt")).unwrap();
+        assert!(txt.contains("Author: rexitTest, Score: 42 (97% upvoted), Comments: 2, Created: 2023-05-06T19:53:19Z, Flair: Frage"));
+        assert!(txt.contains("    - rexitTest (5 points): First!\n        - otherUser (2 points): Second line\n"));
+        assert!(txt.ends_with("Comment by rexitTest on: Testing Rexit, Subreddit: r/rexitTest, Body: Saved comment, Score: 3, Permalink: /r/rexitTest/comments/13a9rn6/testing_rexit/jj1aaaa/\n"));
+
+        let csv = std::fs::read_to_string(out_path.join("saved_posts/saved_posts.csv")).unwrap();
+        assert!(csv.contains(", rexitTest, 42, 0.97, 2, 2023-05-06T19:53:19Z, Frage, false, false, , self.hamburg, t3_134bv4v, post\n"));
+        assert!(csv.ends_with(", [], rexitTest, 3, , , 2023-05-06T19:53:19Z, , , , https://www.reddit.com/r/rexitTest/comments/13a9rn6/testing_rexit/, , t1_jj1aaaa, comment\n"));
+        // Every row has as many columns as the header
+        let columns: Vec<usize> = csv.lines().map(|line| line.split(", ").count()).collect();
+        assert!(columns.iter().all(|count| *count == columns[0]));
+
+        let json = std::fs::read_to_string(out_path.join("saved_posts/saved_posts.json")).unwrap();
+        assert!(json.contains(r#""replies":[{"id":"jj1bbbb""#));
+        assert!(json.contains(r#"{"kind":"comment","id":"t1_jj1aaaa","author":"rexitTest""#));
+        assert!(json.contains(r#""upvote_ratio":0.97"#));
     }
 }
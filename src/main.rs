@@ -15,149 +15,527 @@ use log4rs::encode::pattern::PatternEncoder;
 
 use console::style;
 use export::{export_saved_posts, export_subreddit};
-use inquire::{self, Password, Text};
+use inquire::{self, MultiSelect, Password, Text};
 use log4rs::filter::threshold::ThresholdFilter;
-use std::path::PathBuf;
+use std::path::Path;
+use media_process::MediaOptions;
 use ReAPI::Client;
 
 // import other files
 mod ReAPI;
 mod cli;
 mod export;
-mod image_log;
 mod macros;
+mod media_process;
+mod media_store;
+mod plan;
+mod progress;
+mod summary;
 
-use cli::{Cli, Parser};
+use cli::{Cli, CommandFactory, Parser};
 
 #[tokio::main]
 async fn main() {
     // Parse the CLI args
     let args = Cli::parse();
 
+    // Reject options that do not apply to the subcommand
+    if let Err(message) = args.validate() {
+        Cli::command().error(clap::error::ErrorKind::ArgumentConflict, message).exit();
+    }
+
+    let global = args.global;
+
+    // What was (or with --dry-run would be) exported
+    let mut plan = plan::Plan {
+        comments: global.comments.comments,
+        ..Default::default()
+    };
+
+    // `all` reports each of its parts; verifying media exports nothing
+    let mut summary = summary::Summary::default();
+    let tracked = !matches!(args.command, cli::Commands::All { .. } | cli::Commands::Media { .. });
+    let running = summary.start(args.command.name(), &plan);
+
     // Create an ReAPI client
     let client: Client;
+    // The error of the subcommand; `all` records the errors of its parts
+    let mut result: Result<(), String> = Ok(());
 
     // Init the program
-    if let cli::Commands::Messages {
-        formats,
-        token,
-        images,
-        out,
-        debug,
-        no_usernames,
-        redact,
+    if let cli::Commands::Messages { rooms, filter } = args.command {
+        // Initialize
+        client = init(&global, true).await;
+
+        let filter = ReAPI::MessageFilter {
+            since: filter.since,
+            until: filter.until,
+            from_users: filter.from_users,
+        };
+        result = export_messages(&client, &global, &rooms, &filter, &mut plan).await;
+    } else if let cli::Commands::Saved = args.command {
+        // Initialize
+        client = init(&global, true).await;
+
+        result = export_saved(&client, &global, &Default::default(), &mut plan).await;
+    } else if let cli::Commands::Subreddit {
+        names,
+        sort,
+        meta,
+        listing,
     } = args.command
     {
         // Initialize
-        client = init(debug, token, images, out.clone(), true, redact).await;
+        client = init(&global, false).await;
+
+        result = export_subreddits(&client, &global, &names, meta, &listing_options(sort, listing), &mut plan).await;
+    } else if let cli::Commands::Search {
+        query,
+        subreddit,
+        sort,
+        listing,
+    } = args.command
+    {
+        // Initialize
+        client = init(&global, false).await;
 
         // Creates out folder
-        if !out.join("messages").exists() {
-            std::fs::create_dir(out.join("messages").clone()).unwrap();
-            std::fs::create_dir(out.join("messages/images").clone()).unwrap();
+        let out = &global.out;
+        if !global.dry_run {
+            std::fs::create_dir_all(out.join("search/images")).unwrap();
         }
 
-        // Get list of rooms
-        let rooms = ReAPI::download_rooms(&client, images, no_usernames, out.clone(), redact).await;
-
-        // Exports messages to files.
-        let export_formats: Vec<&str> = formats.split(",").collect();
-
-        // Export chats
-        for room in rooms {
-            for format in export_formats.clone() {
-                match format {
-                    "txt" => export::export_room_chats_txt(room.to_owned(), &out),
-                    "json" => export::export_room_chats_json(room.to_owned(), &out),
-                    "csv" => export::export_room_chats_csv(room.to_owned(), &out),
-                    _ => println!("Not valid Format"),
-                }
-            }
+        // Gets the results
+        let listing = listing_options(sort, listing);
+        let results = ReAPI::download_search(
+            &client,
+            &query,
+            subreddit.as_deref(),
+            &listing,
+            post_options(&global),
+            out.clone(),
+            global.redact,
+        )
+        .await;
+
+        // Export search results, even if they stopped early
+        plan.add_posts("search", &results.items, out);
+        if !global.dry_run {
+            export::export_posts(results.items, &global.formats(), out, "search", "search");
         }
-    } else if let cli::Commands::Saved {
-        formats,
-        token,
-        images,
-        out,
-        debug,
-        no_usernames,
-        redact,
+        result = results.error.map_or(Ok(()), Err);
+    } else if let cli::Commands::User {
+        name,
+        listings,
+        sort,
+        listing,
     } = args.command
     {
+        // The own profile has private listings too
+        let own_profile = name.is_none();
+        let listings = match (listings.is_empty(), own_profile) {
+            (false, _) => listings,
+            (true, true) => ReAPI::UserListing::ALL.to_vec(),
+            (true, false) => ReAPI::UserListing::PUBLIC.to_vec(),
+        };
+
+        // Initialize; private listings and the own name need a login
+        let auth = own_profile || listings.iter().any(|listing| listing.private());
+        client = init(&global, auth).await;
+
+        let name = match name {
+            Some(name) => Ok(ReAPI::user_name(&name).to_owned()),
+            None => ReAPI::whoami(&client, global.redact).await,
+        };
+
+        result = match name {
+            Ok(name) => export_user(&client, &global, &name, &listings, &listing_options(sort, listing), &mut plan).await,
+            Err(error) => Err(error),
+        };
+    } else if let cli::Commands::Inbox = args.command {
         // Initialize
-        client = init(debug, token, images, out.clone(), true, redact).await;
+        client = init(&global, true).await;
 
         // Creates out folder
-        if !out.join("saved_posts").exists() {
-            std::fs::create_dir(out.join("saved_posts").clone()).unwrap();
-            std::fs::create_dir(out.join("saved_posts/images").clone()).unwrap();
+        let out = &global.out;
+        if !global.dry_run {
+            std::fs::create_dir_all(out.join("inbox")).unwrap();
         }
 
-        // Gets saved posts
-        let saved_posts = ReAPI::download_saved_posts(&client, images, out.clone(), redact);
+        // Get the conversations
+        let conversations = ReAPI::download_inbox(&client, global.no_usernames).await;
 
-        let saved_posts = saved_posts.await;
-
-        // Exports messages to files.
-        let export_formats: Vec<&str> = formats.split(",").collect();
-
-        // Export Saved posts
-        export_saved_posts(saved_posts, export_formats, &out);
-    } else if let cli::Commands::Subreddit {
-        name,
-        formats,
-        token,
-        images,
-        out,
-        debug,
-        no_usernames,
-        redact,
+        // Export conversations, even if a box stopped early
+        plan.add_rooms("inbox", &conversations.items, out);
+        if !global.dry_run {
+            export::export_rooms(conversations.items, &global.formats(), &out.join("inbox"));
+        }
+        result = conversations.error.map_or(Ok(()), Err);
+    } else if let cli::Commands::Media {
+        command: cli::MediaCommand::Verify { repair },
     } = args.command
     {
-        // Initialize
-        client = init(debug, token, images, out.clone(), false, redact).await;
+        // Initialize; media downloads need no login
+        client = init(&global, false).await;
+
+        verify_media(&client, &global.out, repair).await;
+    } else if let cli::Commands::All {
+        subreddits,
+        meta,
+        listing,
+        rooms,
+    } = args.command
+    {
+        // Initialize; logs in once for everything
+        client = init(&global, true).await;
+
+        // The date range applies to the chats too
+        let filter = ReAPI::MessageFilter {
+            since: listing.since,
+            until: listing.until,
+            ..Default::default()
+        };
+        // Each part runs even if the ones before it failed
+        let running = summary.start("messages", &plan);
+        let result = export_messages(&client, &global, &rooms, &filter, &mut plan).await;
+        summary.finish(running, &plan, result);
+
+        let running = summary.start("saved", &plan);
+        let result = export_saved(&client, &global, &listing_options(ReAPI::Sort::New, listing.clone()), &mut plan).await;
+        summary.finish(running, &plan, result);
+
+        // The whole history of the own profile
+        let running = summary.start("user", &plan);
+        let result = match ReAPI::whoami(&client, global.redact).await {
+            Ok(name) => {
+                let listing = listing_options(ReAPI::Sort::New, listing.clone());
+                export_user(&client, &global, &name, &ReAPI::UserListing::ALL, &listing, &mut plan).await
+            }
+            Err(error) => Err(error),
+        };
+        summary.finish(running, &plan, result);
+
+        let running = summary.start("subreddit", &plan);
+        let listing = listing_options(ReAPI::Sort::Hot, listing);
+        let result = export_subreddits(&client, &global, &subreddits, meta, &listing, &mut plan).await;
+        summary.finish(running, &plan, result);
+    }
+
+    progress::finish();
+    if tracked {
+        summary.finish(running, &plan, result);
+    }
+
+    if global.dry_run {
+        if !global.json {
+            println!("{}", style("Dry run; nothing was downloaded or written").bold());
+        }
+        plan.print(global.json);
+    } else if !summary.steps.is_empty() {
+        summary.print();
+        summary.write(&global.out);
+    }
+
+    if summary.failed() {
+        crate::exit!(1, "A step failed");
+    }
+}
+
+/// Exports the chats into `messages`
+async fn export_messages(
+    client: &Client,
+    global: &cli::GlobalArgs,
+    args: &cli::RoomArgs,
+    filter: &ReAPI::MessageFilter,
+    plan: &mut plan::Plan,
+) -> Result<(), String> {
+    let out = &global.out;
+
+    // Creates out folder
+    if !global.dry_run {
+        std::fs::create_dir_all(out.join("messages/images")).map_err(|error| format!("Error creating out folder: {error}"))?;
+    }
+
+    // Get list of rooms; names and members are only looked up when needed
+    let room_filter = ReAPI::RoomFilter {
+        rooms: args.rooms.clone(),
+        exclude: args.exclude.clone(),
+        members: args.members.clone(),
+    };
+    let details = args.interactive || !room_filter.is_empty();
+    let mut rooms: Vec<ReAPI::RoomInfo> = ReAPI::list_rooms(client, details)
+        .await?
+        .into_iter()
+        .filter(|room| room_filter.matches(room))
+        .collect();
+
+    if args.interactive {
+        rooms = pick_rooms(rooms);
+    }
+
+    // Media and usernames are not needed for the plan
+    let images = global.images && !global.dry_run;
+    let no_usernames = global.no_usernames || global.dry_run;
+    let rooms = ReAPI::download_rooms(client, &rooms, images, no_usernames, out.clone(), global.redact, filter).await;
+    plan.add_rooms("messages", &rooms.items, out);
+
+    // Export chats, including the messages of rooms that stopped early
+    if !global.dry_run {
+        export::export_rooms(rooms.items, &global.formats(), &out.join("messages"));
+    }
+
+    rooms.error.map_or(Ok(()), Err)
+}
+
+/// Lets the user pick the rooms to export from a list, all of them are selected by default
+fn pick_rooms(rooms: Vec<ReAPI::RoomInfo>) -> Vec<ReAPI::RoomInfo> {
+    let labels: Vec<String> = rooms.iter().map(room_label).collect();
+    let all: Vec<usize> = (0..rooms.len()).collect();
+
+    let picked = MultiSelect::new("Rooms to export", labels)
+        .with_default(&all)
+        .raw_prompt()
+        .expect("Error reading rooms");
+
+    picked.into_iter().map(|option| rooms[option.index].clone()).collect()
+}
+
+/// Name, members and last activity of a room (Example: Weekend plans (otherUser, rexitTest) - 2023-05-06 19:55)
+fn room_label(room: &ReAPI::RoomInfo) -> String {
+    let mut label = room.name.clone().unwrap_or(room.id.clone());
+
+    if !room.members.is_empty() {
+        label.push_str(&format!(" ({})", room.members.join(", ")));
+    }
+    if let Some(last_activity) = room.last_activity {
+        label.push_str(&format!(" - {}", last_activity.format("%Y-%m-%d %H:%M")));
+    }
+
+    label
+}
+
+/// Exports the saved posts and comments into `saved_posts`
+async fn export_saved(
+    client: &Client,
+    global: &cli::GlobalArgs,
+    listing: &ReAPI::ListingOptions,
+    plan: &mut plan::Plan,
+) -> Result<(), String> {
+    let out = &global.out;
+
+    // Creates out folder
+    if !global.dry_run {
+        std::fs::create_dir_all(out.join("saved_posts/images")).map_err(|error| format!("Error creating out folder: {error}"))?;
+    }
+
+    // Gets saved posts
+    let saved_posts = ReAPI::download_saved_posts(client, listing, post_options(global), out.clone(), global.redact).await;
+    plan.add_items("saved_posts", &saved_posts.items, out);
+
+    // Export Saved posts
+    if !global.dry_run {
+        export_saved_posts(saved_posts.items, &global.formats(), out);
+    }
+
+    saved_posts.error.map_or(Ok(()), Err)
+}
+
+/// Exports each subreddit or multireddit into its own `subreddit/<name>` folder
+async fn export_subreddits(
+    client: &Client,
+    global: &cli::GlobalArgs,
+    names: &[String],
+    meta: bool,
+    listing: &ReAPI::ListingOptions,
+    plan: &mut plan::Plan,
+) -> Result<(), String> {
+    let out = &global.out;
+    let mut errors = vec![];
+
+    for name in ReAPI::expand_names(names) {
+        let folder_name = ReAPI::folder_name(&name);
+        let folder = out.join(format!("subreddit/{folder_name}"));
 
         // Creates out folder
-        if !out.join("subreddit").exists() {
-            std::fs::create_dir(out.join("subreddit").clone()).unwrap();
-            std::fs::create_dir(out.join("subreddit/images").clone()).unwrap();
+        if !global.dry_run {
+            std::fs::create_dir_all(folder.join("images")).map_err(|error| format!("Error creating out folder: {error}"))?;
+        }
+
+        // Gets the about page, rules and wiki
+        let with_meta = meta && !ReAPI::is_multireddit(&name);
+        if meta && ReAPI::is_multireddit(&name) {
+            warn!("Multireddits have no about page, rules or wiki; skipping --meta for {name}");
+        } else if with_meta && !global.dry_run {
+            let meta = ReAPI::download_meta(client, &name).await;
+            export::export_subreddit_meta(&meta, &folder);
+        }
+
+        // Gets the posts
+        let subreddit = ReAPI::download_subreddit(client, name.clone(), listing, post_options(global), out.clone(), global.redact).await;
+        plan.add_posts(&format!("subreddit/{folder_name}"), &subreddit.items, out);
+
+        // A dry run only counts the requests for the about page, rules and wiki
+        if with_meta && global.dry_run {
+            plan.add_meta(&format!("subreddit/{folder_name}"), ReAPI::meta_requests(client, &name).await);
+        }
+
+        // Export the posts; the other subreddits are still exported if one fails
+        if !global.dry_run {
+            export_subreddit(subreddit.items, &global.formats(), out, &folder_name);
+        }
+        if let Some(error) = subreddit.error {
+            errors.push(format!("{name}: {error}"));
+        }
+    }
+
+    joined(errors)
+}
+
+/// Exports each listing of a profile into `user/<name>`
+async fn export_user(
+    client: &Client,
+    global: &cli::GlobalArgs,
+    name: &str,
+    listings: &[ReAPI::UserListing],
+    listing: &ReAPI::ListingOptions,
+    plan: &mut plan::Plan,
+) -> Result<(), String> {
+    let out = &global.out;
+    let mut errors = vec![];
+
+    // Creates out folder
+    if !global.dry_run {
+        std::fs::create_dir_all(out.join(format!("user/{name}/images"))).map_err(|error| format!("Error creating out folder: {error}"))?;
+    }
+
+    for &kind in listings {
+        let items = ReAPI::download_user_listing(client, name, kind, listing, post_options(global), out.clone(), global.redact).await;
+        plan.add_items(&format!("user/{name}/{}", kind.as_str()), &items.items, out);
+
+        // Export each listing to its own file
+        if !global.dry_run {
+            export::export_items(items.items, &global.formats(), out, &format!("user/{name}"), kind.as_str());
+        }
+        if let Some(error) = items.error {
+            errors.push(format!("{}: {error}", kind.as_str()));
         }
-        // Gets saved posts
-        let subreddit = ReAPI::download_subreddit(&client, name, images, out.clone(), redact);
+    }
+
+    joined(errors)
+}
 
-        let subreddit = subreddit.await;
+/// The errors of the parts of a step as one
+fn joined(errors: Vec<String>) -> Result<(), String> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+/// What to download along with each post; a dry run only lists the media
+fn post_options(global: &cli::GlobalArgs) -> ReAPI::PostOptions {
+    ReAPI::PostOptions {
+        images: global.images && !global.dry_run,
+        mux_audio: global.mux_audio,
+        no_usernames: global.no_usernames,
+        comments: (global.comments.comments && !global.dry_run).then_some(ReAPI::CommentOptions {
+            depth: global.comments.comment_depth,
+            limit: global.comments.comment_limit,
+        }),
+    }
+}
+
+/// Which posts of a listing to download
+fn listing_options<S>(sort: S, args: cli::ListingArgs) -> ReAPI::ListingOptions<S> {
+    ReAPI::ListingOptions {
+        sort,
+        time: args.time,
+        limit: args.limit,
+        since: args.since,
+        until: args.until,
+    }
+}
 
-        // Exports messages to files.
-        let export_formats: Vec<&str> = formats.split(",").collect();
+/// Checks every file in the media store, downloading broken ones again with `repair`
+async fn verify_media(client: &Client, out: &Path, repair: bool) {
+    let entries = media_store::entries(out);
+    let mut broken = 0;
+    let mut repaired = 0;
+
+    for entry in &entries {
+        let Err(problem) = media_store::verify(out, entry) else {
+            continue;
+        };
+        broken += 1;
+        println!("{} {}: {}", style("BROKEN").red().bold(), entry.path.display(), problem);
+        warn!("Broken media {}: {}", entry.path.display(), problem);
+
+        if !repair {
+            continue;
+        }
 
-        // Export Saved posts
-        export_subreddit(subreddit, export_formats, &out);
+        let fixed = ReAPI::repair_media(client, entry, out)
+            .await
+            .filter(|entry| media_store::verify(out, entry).is_ok());
+        match fixed {
+            Some(_) => {
+                repaired += 1;
+                println!("{} {}", style("REPAIRED").green().bold(), entry.path.display());
+            }
+            None => println!("{} {}", style("FAILED").red().bold(), entry.path.display()),
+        }
+    }
+
+    println!("Checked {} file(s): {} broken", entries.len(), broken);
+    if repair {
+        println!("Repaired {} of {} broken file(s)", repaired, broken);
     }
 }
 
 /// Handles all the init stuff for rexit
-async fn init(debug: bool, token: bool, images: bool, out: PathBuf, auth: bool, redact: bool) -> Client {
+async fn init(global: &cli::GlobalArgs, auth: bool) -> Client {
+    let network = global.network.clone();
+    let (token, redact, out, media) = (global.token, global.redact, &global.out, &global.media);
+
     // Create a Client
-    let mut client = ReAPI::new_client(debug);
+    let debug = network.debug;
+    let mut client = ReAPI::new_client_with(ReAPI::ClientOptions {
+        accept_invalid_certs: network.debug,
+        proxy: network.proxy,
+        ca_cert: network.ca_cert,
+        user_agent: network.user_agent.unwrap_or(ReAPI::USER_AGENT.to_owned()),
+        timeout: std::time::Duration::from_secs(network.timeout),
+    });
+
+    // Point the client somewhere other than Reddit (e.g. a local test server)
+    if let Ok(url) = std::env::var("REXIT_MATRIX_URL") {
+        client.set_matrix_url(&url);
+    }
+    if let Ok(url) = std::env::var("REXIT_REDDIT_URL") {
+        client.set_reddit_url(&url);
+    }
 
     // Handle the debug stuff
     if debug {
-        println!("{}\n{}", 
+        eprintln!("{}\n{}", 
             style("The --debug flag accepts untrusted HTTPS certificates which can be a potential security risk").red().bold(), 
-            style("This option is only recommended if you know what your are doing and you want to debug Rexit").red().bold());
+            style("This option is only recommended if you know what your are doing and you want to debug Rexit; prefer --ca-cert to trust a specific proxy").red().bold());
     }
 
     if redact {
-      println!("{}\n{}", 
+      eprintln!("{}\n{}", 
           style("The --redact flag attempts to redact personal information from the log file.").red().bold(), 
           style("This option is NOT perfect, some personal information may still be present. You have been warned.").red().bold());
   }
 
-    // Initialize logging
-    let level = log::LevelFilter::Info;
+    // Initialize logging; on terminals the progress line replaces the info logs
+    let progress = progress::is_term();
+    progress::init(progress);
+    let level = if progress { log::LevelFilter::Warn } else { log::LevelFilter::Info };
     let file_path = "./rexit.log";
 
-    // Build a stderr logger.
+    // Build a stderr logger; it clears the status line before writing
 
     let stderr = ConsoleAppender::builder()
         .encoder(Box::new(PatternEncoder::new(
@@ -166,31 +544,30 @@ async fn init(debug: bool, token: bool, images: bool, out: PathBuf, auth: bool,
         .target(Target::Stderr)
         .build();
 
-    // Logging to log file.
-    let logfile = FileAppender::builder()
-        // Pattern: https://docs.rs/log4rs/*/log4rs/encode/pattern/index.html
-        .encoder(Box::new(PatternEncoder::new(
-            "{d(%Y-%m-%d %H:%M)(utc)} - {h({l})}: {m}{n}",
-        )))
-        .build(file_path)
-        .unwrap();
-
     // Log Trace level output to file where trace is the default level
     // and the programmatically specified level to stderr.
-    let config = Config::builder()
-        .appender(Appender::builder().build("logfile", Box::new(logfile)))
-        .appender(
-            Appender::builder()
-                .filter(Box::new(ThresholdFilter::new(level)))
-                .build("stderr", Box::new(stderr)),
-        )
-        .build(
-            Root::builder()
-                .appender("logfile")
-                .appender("stderr")
-                .build(LevelFilter::Trace),
-        )
-        .unwrap();
+    let mut config = Config::builder().appender(
+        Appender::builder()
+            .filter(Box::new(ThresholdFilter::new(level)))
+            .build("stderr", Box::new(progress::Logger(stderr))),
+    );
+    let mut root = Root::builder().appender("stderr");
+
+    // Logging to log file; a dry run writes nothing to disk
+    if !global.dry_run {
+        let logfile = FileAppender::builder()
+            // Pattern: https://docs.rs/log4rs/*/log4rs/encode/pattern/index.html
+            .encoder(Box::new(PatternEncoder::new(
+                "{d(%Y-%m-%d %H:%M)(utc)} - {h({l})}: {m}{n}",
+            )))
+            .build(file_path)
+            .unwrap();
+
+        config = config.appender(Appender::builder().build("logfile", Box::new(logfile)));
+        root = root.appender("logfile");
+    }
+
+    let config = config.build(root.build(LevelFilter::Trace)).unwrap();
 
     // Use this to change log levels at runtime.
     // This means you can change the default log level to trace
@@ -198,10 +575,23 @@ async fn init(debug: bool, token: bool, images: bool, out: PathBuf, auth: bool,
     // once you are done.
     let _handle = log4rs::init_config(config);
 
+    // Record or replay the HTTP traffic
+    if let Some(dir) = network.record {
+        info!("Recording requests to {}", dir.display());
+        client.record_to(dir, redact);
+    }
+    let replaying = network.replay.is_some();
+    if let Some(dir) = network.replay {
+        info!("Replaying requests from {}", dir.display());
+        client.replay_from(dir);
+        // The recordings never contain the token, any will do
+        client.login_with_token("replay".to_owned());
+    }
+
     // Authenticate if needed
-    if auth {
+    if auth && !replaying {
         // Handle the three auth flows
-        if token == true {
+        if token {
             // Use the bearer token flow
             trace!("Bearer token auth flow");
 
@@ -241,12 +631,18 @@ async fn init(debug: bool, token: bool, images: bool, out: PathBuf, auth: bool,
     }
 
     // Handle output folder stuff
-    if !out.exists() {
-        std::fs::create_dir(out.clone()).unwrap();
+    if !global.dry_run {
+        std::fs::create_dir_all(out).unwrap();
     }
 
-    // Initialize the image log
-    image_log::init(out.clone());
+    // Load the media store
+    media_store::init(
+        out,
+        MediaOptions {
+            strip_metadata: media.strip_metadata,
+            thumbnails: media.thumbnails,
+        },
+    );
 
-    return client;
+    client
 }
@@ -0,0 +1,263 @@
+//! Optional processing of downloaded media before it is stored: stripping metadata (EXIF incl. GPS, XMP, comments) and generating thumbnails.
+//!
+//! Metadata is removed losslessly by dropping the segments/chunks carrying it, the image data itself is never re-encoded.
+use std::io::Cursor;
+use std::path::Path;
+
+use log::{debug, warn};
+
+/// Longest side of generated thumbnails in pixels
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+/// PNG chunks with metadata
+const PNG_METADATA: [&[u8; 4]; 5] = [b"eXIf", b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+
+/// WebP chunks with metadata
+const WEBP_METADATA: [&[u8; 4]; 2] = [b"EXIF", b"XMP "];
+
+/// What to do with downloaded media
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MediaOptions {
+    pub strip_metadata: bool,
+    pub thumbnails: bool,
+}
+
+/// Removes metadata from JPEG, PNG and WebP files; anything else (or anything that fails to parse) is returned unchanged
+pub fn strip_metadata(data: &[u8]) -> Vec<u8> {
+    let stripped = if data.starts_with(&[0xFF, 0xD8]) {
+        strip_jpeg(data)
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        strip_png(data)
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        strip_webp(data)
+    } else {
+        return data.to_vec();
+    };
+
+    match stripped {
+        Some(stripped) => {
+            debug!("Stripped {} bytes of metadata", data.len() - stripped.len());
+            stripped
+        }
+        None => {
+            warn!("Could not parse image to strip metadata; keeping it as is");
+            data.to_vec()
+        }
+    }
+}
+
+/// Drops the APP1 (EXIF, XMP), APP13 (IPTC) and comment segments
+fn strip_jpeg(data: &[u8]) -> Option<Vec<u8>> {
+    let mut output = data[..2].to_vec();
+    let mut position = 2;
+
+    loop {
+        if *data.get(position)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(position + 1)?;
+
+        match marker {
+            // Fill byte
+            0xFF => {
+                position += 1;
+                continue;
+            }
+            // Markers without a length
+            0x01 | 0xD0..=0xD7 => {
+                output.extend_from_slice(&data[position..position + 2]);
+                position += 2;
+                continue;
+            }
+            // Start of scan / end of image: the rest is image data
+            0xDA | 0xD9 => {
+                output.extend_from_slice(&data[position..]);
+                return Some(output);
+            }
+            _ => {}
+        }
+
+        let length = u16::from_be_bytes([*data.get(position + 2)?, *data.get(position + 3)?]) as usize;
+        let end = position + 2 + length;
+        let segment = data.get(position..end)?;
+
+        if !matches!(marker, 0xE1 | 0xED | 0xFE) {
+            output.extend_from_slice(segment);
+        }
+        position = end;
+    }
+}
+
+fn strip_png(data: &[u8]) -> Option<Vec<u8>> {
+    let mut output = data[..8].to_vec();
+    let mut position = 8;
+
+    while position < data.len() {
+        let length = u32::from_be_bytes(data.get(position..position + 4)?.try_into().ok()?) as usize;
+        let kind = data.get(position + 4..position + 8)?;
+        // Length, type, data and CRC
+        let end = position + 12 + length;
+        let chunk = data.get(position..end)?;
+
+        if !PNG_METADATA.iter().any(|metadata| metadata.as_slice() == kind) {
+            output.extend_from_slice(chunk);
+        }
+        position = end;
+    }
+
+    Some(output)
+}
+
+fn strip_webp(data: &[u8]) -> Option<Vec<u8>> {
+    let mut output = data[..12].to_vec();
+    let mut position = 12;
+
+    while position < data.len() {
+        let kind = data.get(position..position + 4)?;
+        let length = u32::from_le_bytes(data.get(position + 4..position + 8)?.try_into().ok()?) as usize;
+        // Chunks are padded to an even size
+        let end = (position + 8 + length + (length & 1)).min(data.len());
+        let chunk = data.get(position..end)?;
+
+        if kind == b"VP8X" {
+            // Clear the EXIF and XMP flags
+            let mut chunk = chunk.to_vec();
+            *chunk.get_mut(8)? &= !(0x08 | 0x04);
+            output.extend_from_slice(&chunk);
+        } else if !WEBP_METADATA.iter().any(|metadata| metadata.as_slice() == kind) {
+            output.extend_from_slice(chunk);
+        }
+        position = end;
+    }
+
+    // Fix the size of the RIFF container
+    let size = (output.len() - 8) as u32;
+    output[4..8].copy_from_slice(&size.to_le_bytes());
+
+    Some(output)
+}
+
+/// Width and height of an image, read from its header
+pub fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    image::io::Reader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// Writes a JPEG thumbnail of an image to `path`; returns false for anything that is not a decodable image
+pub fn thumbnail(data: &[u8], path: &Path) -> bool {
+    let Ok(image) = image::load_from_memory(data) else {
+        return false;
+    };
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).expect("Error creating thumbnail folder");
+    }
+
+    // JPEG has no alpha channel
+    let thumbnail = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgb8();
+    match thumbnail.save_with_format(path, image::ImageFormat::Jpeg) {
+        Ok(()) => true,
+        Err(error) => {
+            warn!("Failed to write thumbnail: {error}");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    fn encode(format: image::ImageFormat) -> Vec<u8> {
+        let image = image::DynamicImage::new_rgb8(4, 3);
+        let mut data = Cursor::new(Vec::new());
+        image.write_to(&mut data, format).unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn strip_jpeg() {
+        let jpeg = encode(image::ImageFormat::Jpeg);
+
+        // Insert an EXIF segment with a GPS tag after SOI
+        let exif = b"Exif\0\0GPSLatitude";
+        let mut data = jpeg[..2].to_vec();
+        data.extend_from_slice(&[0xFF, 0xE1]);
+        data.extend_from_slice(&((exif.len() + 2) as u16).to_be_bytes());
+        data.extend_from_slice(exif);
+        data.extend_from_slice(&jpeg[2..]);
+
+        let stripped = super::strip_metadata(&data);
+
+        assert_eq!(stripped, jpeg);
+        assert!(image::load_from_memory(&stripped).is_ok());
+    }
+
+    #[test]
+    fn strip_png() {
+        let png = encode(image::ImageFormat::Png);
+
+        // Insert a text chunk after IHDR (8 signature + 25 IHDR bytes)
+        let mut data = png[..33].to_vec();
+        data.extend_from_slice(&10u32.to_be_bytes());
+        data.extend_from_slice(b"tEXtComment\0ab");
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&png[33..]);
+
+        let stripped = super::strip_metadata(&data);
+
+        assert_eq!(stripped, png);
+    }
+
+    #[test]
+    fn strip_webp() {
+        // VP8X with the EXIF flag set, an image chunk and an EXIF chunk
+        let mut data = b"RIFF\0\0\0\0WEBP".to_vec();
+        data.extend_from_slice(b"VP8X");
+        data.extend_from_slice(&10u32.to_le_bytes());
+        data.extend_from_slice(&[0x08, 0, 0, 0, 3, 0, 0, 2, 0, 0]);
+        data.extend_from_slice(b"VP8L");
+        data.extend_from_slice(&3u32.to_le_bytes());
+        data.extend_from_slice(&[1, 2, 3, 0]);
+        data.extend_from_slice(b"EXIF");
+        data.extend_from_slice(&4u32.to_le_bytes());
+        data.extend_from_slice(b"GPS!");
+        let size = (data.len() - 8) as u32;
+        data[4..8].copy_from_slice(&size.to_le_bytes());
+
+        let stripped = super::strip_metadata(&data);
+
+        assert_eq!(stripped.len(), data.len() - 12);
+        assert_eq!(&stripped[4..8], &((stripped.len() - 8) as u32).to_le_bytes());
+        assert_eq!(stripped[20], 0);
+        assert!(!stripped.windows(4).any(|window| window == b"EXIF"));
+    }
+
+    #[test]
+    fn other_files_are_unchanged() {
+        assert_eq!(super::strip_metadata(b"not an image"), b"not an image");
+        // Truncated images are kept as they are
+        let jpeg = encode(image::ImageFormat::Jpeg);
+        assert_eq!(super::strip_metadata(&jpeg[..5]), &jpeg[..5]);
+    }
+
+    #[test]
+    fn thumbnail() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = image::DynamicImage::new_rgba8(1024, 512);
+        let mut data = Cursor::new(Vec::new());
+        image.write_to(&mut data, image::ImageFormat::Png).unwrap();
+        let data = data.into_inner();
+
+        assert_eq!(super::dimensions(&data), Some((1024, 512)));
+
+        let path = dir.path().join("thumbnails/a.jpg");
+        assert!(super::thumbnail(&data, &path));
+        assert_eq!(super::dimensions(&std::fs::read(path).unwrap()), Some((256, 128)));
+
+        assert!(!super::thumbnail(b"not an image", &dir.path().join("b.jpg")));
+    }
+}
@@ -0,0 +1,389 @@
+//! Content-addressed store for downloaded media, preventing duplicates within and between runs.
+//!
+//! Every file is identified by the SHA-256 of its contents. The manifest (`media.jsonl` in the out folder) maps each
+//! source URL / mxc URI to that hash and the hash to the file it was saved as, so identical media from rooms, saved posts
+//! and subreddits is only stored once. Entries are appended only after the file was written successfully.
+use crate::media_process::{self, MediaOptions};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::{fs::OpenOptions, sync::OnceLock};
+
+/// Name of the manifest inside the out folder
+pub const MANIFEST: &str = "media.jsonl";
+
+/// A line of the manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaEntry {
+    /// The URL or mxc URI the media was downloaded from
+    pub source: String,
+    pub sha256: String,
+    /// Path of the file relative to the out folder
+    pub path: PathBuf,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// Path of the thumbnail relative to the out folder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<PathBuf>,
+}
+
+#[derive(Debug, Default)]
+struct Manifest {
+    sources: HashMap<String, String>,
+    files: HashMap<String, MediaEntry>,
+}
+
+impl Manifest {
+    fn load(out: &Path) -> Manifest {
+        let mut manifest = Manifest::default();
+
+        let Ok(contents) = std::fs::read_to_string(out.join(MANIFEST)) else {
+            return manifest;
+        };
+
+        // A crash while appending can leave a broken line; the rest of the manifest is still good
+        for (number, line) in contents.lines().enumerate().filter(|(_, line)| !line.is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(entry) => manifest.insert(entry),
+                Err(error) => warn!("Skipping broken line {} of the media manifest: {error}", number + 1),
+            }
+        }
+
+        manifest
+    }
+
+    /// Later entries win, so a re-downloaded file replaces the missing one
+    fn insert(&mut self, entry: MediaEntry) {
+        self.sources.insert(entry.source.clone(), entry.sha256.clone());
+        self.files.insert(entry.sha256.clone(), entry);
+    }
+}
+
+/// The manifests of every out folder used in this run, each read from disk once
+fn manifests() -> &'static Mutex<HashMap<PathBuf, Manifest>> {
+    static MANIFESTS: OnceLock<Mutex<HashMap<PathBuf, Manifest>>> = OnceLock::new();
+    MANIFESTS.get_or_init(Default::default)
+}
+
+fn with_manifest<T>(out: &Path, f: impl FnOnce(&mut Manifest) -> T) -> T {
+    let mut manifests = manifests().lock().unwrap();
+    let manifest = manifests
+        .entry(out.to_path_buf())
+        .or_insert_with(|| Manifest::load(out));
+
+    f(manifest)
+}
+
+/// How new media is processed before it is stored
+fn options() -> &'static Mutex<MediaOptions> {
+    static OPTIONS: OnceLock<Mutex<MediaOptions>> = OnceLock::new();
+    OPTIONS.get_or_init(Default::default)
+}
+
+/// Loads the manifest of the out folder and sets how new media is processed
+pub fn init(out: &Path, media_options: MediaOptions) {
+    info!("Initializing Media Store");
+
+    *options().lock().unwrap() = media_options;
+
+    let count = with_manifest(out, |manifest| manifest.files.len());
+    debug!("Media store contains {count} file(s)");
+}
+
+/// Every file in the store
+pub fn entries(out: &Path) -> Vec<MediaEntry> {
+    let mut entries: Vec<MediaEntry> = with_manifest(out, |manifest| manifest.files.values().cloned().collect());
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries
+}
+
+/// Forgets a file, so its sources are downloaded again; its lines are removed from the manifest on disk as well
+pub fn remove(out: &Path, entry: &MediaEntry) {
+    with_manifest(out, |manifest| {
+        manifest.files.remove(&entry.sha256);
+        manifest.sources.retain(|_, hash| *hash != entry.sha256);
+
+        // Broken lines are kept as they are
+        let path = out.join(MANIFEST);
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return;
+        };
+        let kept: String = contents
+            .lines()
+            .filter(|line| serde_json::from_str::<MediaEntry>(line).ok().is_none_or(|line| line.sha256 != entry.sha256))
+            .map(|line| format!("{line}\n"))
+            .collect();
+
+        // Replaces the manifest in one step, so a crash can not lose the other entries
+        let temporary = out.join(format!("{MANIFEST}.tmp"));
+        if let Err(error) = std::fs::write(&temporary, kept).and_then(|_| std::fs::rename(&temporary, &path)) {
+            warn!("Could not remove {} from the media manifest: {error}", entry.path.display());
+        }
+    });
+}
+
+/// Reads the manifest from disk again, as the next run would
+#[cfg(test)]
+pub fn reload(out: &Path) {
+    manifests().lock().unwrap().remove(out);
+}
+
+/// What is wrong with a stored file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    Missing,
+    Size { expected: u64, actual: u64 },
+    Hash,
+    /// The file does not decode as the type its extension claims
+    Corrupt(String),
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Missing => write!(f, "missing"),
+            Problem::Size { expected, actual } => write!(f, "expected {expected} bytes, found {actual}"),
+            Problem::Hash => write!(f, "contents changed (SHA-256 mismatch)"),
+            Problem::Corrupt(reason) => write!(f, "corrupt: {reason}"),
+        }
+    }
+}
+
+/// Checks that a stored file exists, matches its recorded size and hash and decodes as its type
+pub fn verify(out: &Path, entry: &MediaEntry) -> Result<(), Problem> {
+    let Ok(data) = std::fs::read(out.join(&entry.path)) else {
+        return Err(Problem::Missing);
+    };
+
+    if data.len() as u64 != entry.size {
+        return Err(Problem::Size {
+            expected: entry.size,
+            actual: data.len() as u64,
+        });
+    }
+
+    if format!("{:x}", Sha256::digest(&data)) != entry.sha256 {
+        return Err(Problem::Hash);
+    }
+
+    let extension = entry
+        .path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    check_type(&extension, &data).map_err(Problem::Corrupt)
+}
+
+/// Images are fully decoded, other types only have their signature checked
+fn check_type(extension: &str, data: &[u8]) -> Result<(), String> {
+    if let Some(format) = image::ImageFormat::from_extension(extension).filter(|format| format.can_read()) {
+        return image::load_from_memory_with_format(data, format)
+            .map(|_| ())
+            .map_err(|error| error.to_string());
+    }
+
+    let valid = match extension {
+        "mp4" | "m4a" | "mov" => data.get(4..8) == Some(b"ftyp"),
+        "webm" | "weba" => data.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]),
+        "ogg" => data.starts_with(b"OggS"),
+        "mp3" => data.starts_with(b"ID3") || data.first() == Some(&0xFF),
+        "wav" => data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WAVE"),
+        "pdf" => data.starts_with(b"%PDF"),
+        "zip" => data.starts_with(b"PK"),
+        // Nothing to check for everything else
+        _ => true,
+    };
+
+    match valid {
+        true => Ok(()),
+        false => Err(format!("not a .{extension} file")),
+    }
+}
+
+/// Returns the stored file of a source, if it was already downloaded and is still on disk
+pub fn lookup(out: &Path, source: &str) -> Option<MediaEntry> {
+    with_manifest(out, |manifest| {
+        let hash = manifest.sources.get(source)?;
+        manifest.files.get(hash).cloned()
+    })
+    .filter(|entry| out.join(&entry.path).exists())
+}
+
+/// Stores downloaded media at `path` (inside the out folder) unless identical content is already stored.
+///
+/// Returns the entry of the file the source now points to.
+pub fn store(out: &Path, source: &str, data: &[u8], path: &Path) -> MediaEntry {
+    let media_options = *options().lock().unwrap();
+
+    // Processing is deterministic, so identical downloads still end up with the same hash
+    let data = match media_options.strip_metadata {
+        true => media_process::strip_metadata(data),
+        false => data.to_vec(),
+    };
+
+    let sha256 = format!("{:x}", Sha256::digest(&data));
+
+    let existing = with_manifest(out, |manifest| manifest.files.get(&sha256).cloned())
+        .filter(|entry| out.join(&entry.path).exists());
+
+    let entry = match existing {
+        Some(existing) => {
+            debug!("Identical media already stored at {}", existing.path.display());
+            MediaEntry {
+                source: source.to_owned(),
+                ..existing
+            }
+        }
+        None => {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).expect("Error creating media folder");
+            }
+
+            // Write to a temporary file first so a crash never leaves a truncated file behind
+            let temporary = path.with_extension("part");
+            std::fs::write(&temporary, &data).expect("Error writing media");
+            std::fs::rename(&temporary, path).expect("Error writing media");
+
+            let dimensions = media_process::dimensions(&data);
+
+            let thumbnail = PathBuf::from("thumbnails").join(&sha256).with_extension("jpg");
+            let thumbnail = (media_options.thumbnails && media_process::thumbnail(&data, &out.join(&thumbnail)))
+                .then_some(thumbnail);
+
+            MediaEntry {
+                source: source.to_owned(),
+                sha256,
+                path: path.strip_prefix(out).unwrap_or(path).to_path_buf(),
+                size: data.len() as u64,
+                width: dimensions.map(|(width, _)| width),
+                height: dimensions.map(|(_, height)| height),
+                thumbnail,
+            }
+        }
+    };
+
+    append(out, &entry);
+    with_manifest(out, |manifest| manifest.insert(entry.clone()));
+
+    entry
+}
+
+/// Path of a stored file (or its thumbnail) as seen from `folder` (both relative to the out folder), with forward slashes for the exports
+pub fn relative_path(path: &Path, folder: &Path) -> String {
+    let path = match path.strip_prefix(folder) {
+        Ok(path) => path.to_path_buf(),
+        // Stored by another subcommand, e.g. "../saved_posts/images/a.png"
+        Err(_) => folder
+            .components()
+            .map(|_| Path::new(".."))
+            .collect::<PathBuf>()
+            .join(path),
+    };
+
+    path.to_string_lossy().replace('\\', "/")
+}
+
+fn append(out: &Path, entry: &MediaEntry) {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(out.join(MANIFEST))
+        .expect("Error writing to media manifest");
+
+    // Start a new line after a truncated last line, so the entry is not lost with it
+    let truncated = std::fs::read(out.join(MANIFEST)).is_ok_and(|contents| contents.last().is_some_and(|last| *last != b'\n'));
+    if truncated {
+        writeln!(file).expect("Error writing to media manifest");
+    }
+
+    writeln!(file, "{}", serde_json::to_string(entry).unwrap()).expect("Error writing to media manifest");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    #[test]
+    fn deduplicates_identical_content() {
+        let out = tempfile::tempdir().unwrap();
+        let out = out.path();
+        std::fs::create_dir_all(out.join("messages/images")).unwrap();
+        std::fs::create_dir_all(out.join("subreddit/images")).unwrap();
+
+        let first = super::store(out, "mxc://reddit.com/a", b"image", &out.join("messages/images/a.png"));
+        let second = super::store(out, "https://i.redd.it/b.png", b"image", &out.join("subreddit/images/b.png"));
+
+        assert_eq!(first.sha256, second.sha256);
+        assert_eq!(second.path, Path::new("messages/images/a.png"));
+        assert!(!out.join("subreddit/images/b.png").exists());
+
+        assert_eq!(super::lookup(out, "https://i.redd.it/b.png").unwrap().path, first.path);
+        assert!(super::lookup(out, "https://i.redd.it/c.png").is_none());
+
+        assert_eq!(super::relative_path(&second.path, Path::new("messages")), "images/a.png");
+        assert_eq!(super::relative_path(&second.path, Path::new("subreddit/r_rexitTest")), "../../messages/images/a.png");
+        assert_eq!(super::relative_path(Path::new("thumbnails/a.jpg"), Path::new("messages")), "../thumbnails/a.jpg");
+    }
+
+    #[test]
+    fn verify() {
+        let out = tempfile::tempdir().unwrap();
+        let out = out.path();
+        let png = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/test_resources/test_cases/ReAPI/mock_server/image.png")).unwrap();
+
+        let good = super::store(out, "good", &png, &out.join("good.png"));
+        assert_eq!(super::verify(out, &good), Ok(()));
+
+        let truncated = super::store(out, "truncated", &png[..40], &out.join("truncated.png"));
+        assert!(matches!(super::verify(out, &truncated), Err(super::Problem::Corrupt(_))));
+
+        let video = super::store(out, "video", b"not really a video", &out.join("video.mp4"));
+        assert!(matches!(super::verify(out, &video), Err(super::Problem::Corrupt(_))));
+
+        std::fs::write(out.join("good.png"), &png[..10]).unwrap();
+        assert_eq!(super::verify(out, &good), Err(super::Problem::Size { expected: 70, actual: 10 }));
+
+        std::fs::remove_file(out.join("good.png")).unwrap();
+        assert_eq!(super::verify(out, &good), Err(super::Problem::Missing));
+    }
+
+    #[test]
+    fn manifest_survives_restart() {
+        let out = tempfile::tempdir().unwrap();
+        let out = out.path();
+
+        super::store(out, "mxc://reddit.com/a", b"image", &out.join("a.png"));
+
+        let manifest = super::Manifest::load(out);
+        assert_eq!(manifest.sources.len(), 1);
+        assert_eq!(manifest.files.values().next().unwrap().size, 5);
+    }
+
+    #[test]
+    fn manifest_survives_truncated_line() {
+        let out = tempfile::tempdir().unwrap();
+        let out = out.path();
+
+        let entry = super::store(out, "mxc://reddit.com/a", b"image", &out.join("a.png"));
+        let mut contents = std::fs::read_to_string(out.join(super::MANIFEST)).unwrap();
+        contents.push_str(r#"{"source":"mxc://reddit.com/b","sha"#);
+        std::fs::write(out.join(super::MANIFEST), contents).unwrap();
+
+        // The broken line is skipped, the entries around it are kept
+        super::append(out, &super::MediaEntry {
+            source: "mxc://reddit.com/c".to_owned(),
+            ..entry
+        });
+        let manifest = super::Manifest::load(out);
+        assert_eq!(manifest.sources.len(), 2);
+        assert!(manifest.sources.contains_key("mxc://reddit.com/c"));
+    }
+}
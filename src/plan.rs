@@ -0,0 +1,208 @@
+//! Counts what an export fetches; printed instead of exporting with `--dry-run`.
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::media_store;
+use crate::ReAPI;
+
+/// What one folder of an export (Example: messages, subreddit/rust) holds
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct Section {
+    /// Relative to the out folder
+    pub folder: String,
+    /// Rooms or conversations; None for listings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rooms: Option<usize>,
+    /// Messages, posts or comments
+    pub items: usize,
+    /// Media referenced by the items
+    pub media: usize,
+    /// Media that is not in the media store yet
+    pub new_media: usize,
+    /// Size of the new media as far as it is known (chats announce it, posts do not)
+    pub bytes: u64,
+    /// Further requests for the comments of posts and the about page, rules and wiki (at least, comments can have more to load)
+    pub requests: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Plan {
+    pub sections: Vec<Section>,
+    /// Whether the comments of posts are downloaded, which takes a request per post with comments
+    #[serde(skip)]
+    pub comments: bool,
+}
+
+impl Plan {
+    pub fn add_rooms(&mut self, folder: &str, rooms: &[ReAPI::Room], out: &Path) {
+        let mut section = Section {
+            folder: folder.to_owned(),
+            rooms: Some(rooms.len()),
+            ..Default::default()
+        };
+
+        for message in rooms.iter().flat_map(|room| room.messages()) {
+            section.items += 1;
+
+            if let ReAPI::Content::Media(media) = message.content {
+                section.media += 1;
+                if media_store::lookup(out, &media.mxc).is_none() {
+                    section.new_media += 1;
+                    section.bytes += media.size.unwrap_or_default();
+                }
+            }
+        }
+
+        self.sections.push(section);
+    }
+
+    pub fn add_posts(&mut self, folder: &str, posts: &[ReAPI::Post], out: &Path) {
+        let mut section = Section {
+            folder: folder.to_owned(),
+            ..Default::default()
+        };
+
+        for post in posts {
+            section.add_post(post, self.comments, out);
+        }
+
+        self.sections.push(section);
+    }
+
+    pub fn add_items(&mut self, folder: &str, items: &[ReAPI::SavedItem], out: &Path) {
+        let mut section = Section {
+            folder: folder.to_owned(),
+            ..Default::default()
+        };
+
+        for item in items {
+            match item {
+                ReAPI::SavedItem::Post(post) => section.add_post(post, self.comments, out),
+                ReAPI::SavedItem::Comment(_) => section.items += 1,
+            }
+        }
+
+        self.sections.push(section);
+    }
+
+    /// Adds the requests for the about page, rules and wiki of a subreddit to its section
+    pub fn add_meta(&mut self, folder: &str, requests: usize) {
+        match self.sections.iter_mut().find(|section| section.folder == folder) {
+            Some(section) => section.requests += requests,
+            None => self.sections.push(Section {
+                folder: folder.to_owned(),
+                requests,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// All sections added up
+    pub fn total(&self) -> Section {
+        let mut total = Section {
+            folder: "total".to_owned(),
+            ..Default::default()
+        };
+
+        for section in &self.sections {
+            if let Some(rooms) = section.rooms {
+                total.rooms = Some(total.rooms.unwrap_or_default() + rooms);
+            }
+            total.items += section.items;
+            total.media += section.media;
+            total.new_media += section.new_media;
+            total.bytes += section.bytes;
+            total.requests += section.requests;
+        }
+
+        total
+    }
+
+    /// Prints a line per section and the total, or the plan as JSON
+    pub fn print(&self, json: bool) {
+        if json {
+            println!("{}", serde_json::to_string_pretty(self).expect("Error serializing plan"));
+            return;
+        }
+
+        for section in self.sections.iter().chain([&self.total()]) {
+            println!("  {}", section.line());
+        }
+    }
+}
+
+impl Section {
+    fn add_post(&mut self, post: &ReAPI::Post, comments: bool, out: &Path) {
+        self.items += 1;
+        if comments && post.num_comments > 0 {
+            self.requests += 1;
+        }
+        self.media += post.img_url.len();
+        self.new_media += post
+            .img_url
+            .iter()
+            .filter(|url| media_store::lookup(out, url).is_none())
+            .count();
+    }
+
+    /// Example: subreddit/rust: 120 item(s), 14 media (10 new, at least 2.3 MB), 45 more request(s)
+    pub fn line(&self) -> String {
+        let rooms = match self.rooms {
+            Some(rooms) => format!("{rooms} room(s), "),
+            None => String::new(),
+        };
+        let requests = match self.requests {
+            0 => String::new(),
+            requests => format!(", {requests} more request(s)"),
+        };
+
+        format!(
+            "{}: {rooms}{} item(s), {} media ({} new, at least {:.1} MB){requests}",
+            self.folder,
+            self.items,
+            self.media,
+            self.new_media,
+            self.bytes as f64 / 1_000_000.0
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn plan() {
+        let (client, _server) = crate::ReAPI::mock::mock_client().await;
+        let out = tempfile::tempdir().unwrap();
+
+        let mut plan = super::Plan::default();
+
+        let rooms = crate::ReAPI::list_rooms(&client, false).await.unwrap();
+        let rooms = crate::ReAPI::download_rooms(&client, &rooms, false, true, PathBuf::from(out.path()), false, &Default::default()).await.items;
+        plan.add_rooms("messages", &rooms, out.path());
+
+        // With --comments and --meta one post has comments to fetch, plus the about page, rules and wiki
+        plan.comments = true;
+        let posts = crate::ReAPI::download_subreddit(&client, "r/rexitTest".to_owned(), &Default::default(), Default::default(), PathBuf::from(out.path()), false).await.items;
+        plan.add_posts("subreddit/rexitTest", &posts, out.path());
+        plan.add_meta("subreddit/rexitTest", crate::ReAPI::meta_requests(&client, "r/rexitTest").await);
+
+        // The video and the image announce their sizes
+        assert_eq!(plan.sections[0].line(), "messages: 1 room(s), 3 item(s), 2 media (2 new, at least 0.0 MB)");
+        assert_eq!(plan.sections[0].bytes, 85);
+        assert_eq!(plan.sections[1].items, 2);
+        assert_eq!(plan.sections[1].media, 1);
+        assert_eq!(plan.sections[1].line(), "subreddit/rexitTest: 2 item(s), 1 media (1 new, at least 0.0 MB), 6 more request(s)");
+
+        let total = plan.total();
+        assert_eq!(total.rooms, Some(1));
+        assert_eq!(total.items, 5);
+        assert_eq!(total.new_media, 3);
+        assert_eq!(total.requests, 6);
+
+        // Nothing was written
+        assert_eq!(std::fs::read_dir(out.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,234 @@
+//! Counts what a run fetches and shows it as a status line on terminals.
+use std::sync::{Mutex, OnceLock};
+
+use console::Term;
+use log::{Log, Metadata, Record};
+use log4rs::append::console::ConsoleAppender;
+use log4rs::append::Append;
+use serde::Serialize;
+
+/// Running totals of a run
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq)]
+pub struct Counts {
+    /// Chat messages fetched
+    pub messages: usize,
+    /// Posts and comments of listings fetched
+    pub items: usize,
+    /// Media files downloaded
+    pub media: usize,
+    /// Size of the downloaded media
+    pub bytes: u64,
+    /// Media that was already downloaded
+    pub skipped: usize,
+    /// Media that could not be downloaded
+    pub failures: usize,
+}
+
+impl Counts {
+    /// What was counted since `earlier`
+    pub fn since(&self, earlier: &Counts) -> Counts {
+        Counts {
+            messages: self.messages - earlier.messages,
+            items: self.items - earlier.items,
+            media: self.media - earlier.media,
+            bytes: self.bytes - earlier.bytes,
+            skipped: self.skipped - earlier.skipped,
+            failures: self.failures - earlier.failures,
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// Whether the status line is drawn
+    enabled: bool,
+    /// Example: room 2/5
+    stage: String,
+    /// The totals when the stage started
+    stage_start: Counts,
+    counts: Counts,
+    /// Whether the status line is on the screen
+    drawn: bool,
+}
+
+fn state() -> &'static Mutex<State> {
+    static STATE: OnceLock<Mutex<State>> = OnceLock::new();
+    STATE.get_or_init(Default::default)
+}
+
+/// Whether stderr is a terminal the status line can be drawn on
+pub fn is_term() -> bool {
+    Term::stderr().is_term()
+}
+
+/// Turns the status line on or off; it is off until enabled
+pub fn init(enabled: bool) {
+    state().lock().unwrap().enabled = enabled;
+}
+
+/// Sets what is being worked on (Example: room 2/5); the status line counts what it fetches separately
+pub fn stage(stage: String) {
+    update(|state| {
+        state.stage = stage;
+        state.stage_start = state.counts;
+    });
+}
+
+/// What was counted since the stage started
+pub fn stage_counts() -> Counts {
+    let state = state().lock().unwrap();
+    state.counts.since(&state.stage_start)
+}
+
+pub fn messages(count: usize) {
+    update(|state| state.counts.messages += count);
+}
+
+pub fn items(count: usize) {
+    update(|state| state.counts.items += count);
+}
+
+pub fn media_downloaded(bytes: usize) {
+    update(|state| {
+        state.counts.media += 1;
+        state.counts.bytes += bytes as u64;
+    });
+}
+
+pub fn media_skipped() {
+    update(|state| state.counts.skipped += 1);
+}
+
+pub fn media_failed() {
+    update(|state| state.counts.failures += 1);
+}
+
+/// The totals so far
+pub fn counts() -> Counts {
+    state().lock().unwrap().counts
+}
+
+/// Removes the status line
+pub fn finish() {
+    let mut state = state().lock().unwrap();
+    if state.drawn {
+        let _ = Term::stderr().clear_line();
+        state.drawn = false;
+    }
+    state.stage.clear();
+}
+
+/// Runs `f` (Example: writing a log line to stderr) with the status line removed, then draws it again
+pub fn suspend<T>(f: impl FnOnce() -> T) -> T {
+    let state = state().lock().unwrap();
+    if !state.drawn {
+        return f();
+    }
+
+    let _ = Term::stderr().clear_line();
+    let result = f();
+    draw(&state);
+
+    result
+}
+
+fn update(f: impl FnOnce(&mut State)) {
+    let mut state = state().lock().unwrap();
+    f(&mut state);
+
+    if state.enabled {
+        let _ = Term::stderr().clear_line();
+        draw(&state);
+        state.drawn = true;
+    }
+}
+
+fn draw(state: &State) {
+    let stage = match state.stage.as_str() {
+        "" => String::new(),
+        stage => stage_line(stage, &state.counts.since(&state.stage_start)),
+    };
+    let _ = Term::stderr().write_str(&line(&stage, &state.counts));
+}
+
+/// Writes log records to stderr without mixing them into the status line
+#[derive(Debug)]
+pub struct Logger(pub ConsoleAppender);
+
+impl Log for Logger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        suspend(|| {
+            let _ = self.0.append(record);
+        });
+    }
+
+    fn flush(&self) {
+        Append::flush(&self.0);
+    }
+}
+
+/// Example: room 2/5: 300 message(s), 4 media
+fn stage_line(stage: &str, counts: &Counts) -> String {
+    format!("{stage}: {} message(s), {} media", counts.messages, counts.media + counts.skipped)
+}
+
+/// Example: room 2/5: 300 message(s), 4 media | 1200 message(s) | 0 item(s) | 14 media (2.3 MB), 3 skipped, 1 failed
+fn line(stage: &str, counts: &Counts) -> String {
+    let mut line = format!(
+        "{} message(s) | {} item(s) | {} media ({:.1} MB), {} skipped, {} failed",
+        counts.messages,
+        counts.items,
+        counts.media,
+        counts.bytes as f64 / 1_000_000.0,
+        counts.skipped,
+        counts.failures
+    );
+
+    if !stage.is_empty() {
+        line = format!("{stage} | {line}");
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Counts;
+
+    #[test]
+    fn line() {
+        let counts = Counts {
+            messages: 1200,
+            media: 14,
+            bytes: 2_345_678,
+            skipped: 3,
+            failures: 1,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            super::line("room 2/5", &counts),
+            "room 2/5 | 1200 message(s) | 0 item(s) | 14 media (2.3 MB), 3 skipped, 1 failed"
+        );
+
+        // The current room counts on its own, media that was already downloaded included
+        let room = Counts {
+            messages: 300,
+            media: 3,
+            skipped: 1,
+            ..Default::default()
+        };
+        assert_eq!(super::stage_line("room 2/5", &room), "room 2/5: 300 message(s), 4 media");
+
+        let earlier = Counts {
+            messages: 200,
+            ..Default::default()
+        };
+        assert_eq!(counts.since(&earlier).messages, 1000);
+        assert_eq!(counts.since(&earlier).media, 14);
+    }
+}
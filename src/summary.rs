@@ -0,0 +1,147 @@
+//! The report of a run, printed at the end and saved as `summary.json` in the out folder.
+use std::path::Path;
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use console::style;
+use log::error;
+use serde::Serialize;
+
+use crate::plan::{Plan, Section};
+use crate::progress::{self, Counts};
+
+/// What one subcommand (or part of `all`) exported
+#[derive(Debug, Clone, Serialize)]
+pub struct Step {
+    pub command: String,
+    pub duration_secs: f64,
+    /// The exported folders
+    pub sections: Vec<Section>,
+    /// What was fetched and downloaded
+    pub counts: Counts,
+    /// Why the step stopped early (Example: a private subreddit)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A step that is still running
+pub struct Running {
+    command: String,
+    started: Instant,
+    counts: Counts,
+    sections: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary {
+    pub started: DateTime<Utc>,
+    pub duration_secs: f64,
+    pub steps: Vec<Step>,
+    #[serde(skip)]
+    started_instant: Instant,
+}
+
+impl Default for Summary {
+    fn default() -> Self {
+        Summary {
+            started: Utc::now(),
+            duration_secs: 0.0,
+            steps: vec![],
+            started_instant: Instant::now(),
+        }
+    }
+}
+
+impl Summary {
+    pub fn start(&self, command: &str, plan: &Plan) -> Running {
+        Running {
+            command: command.to_owned(),
+            started: Instant::now(),
+            counts: progress::counts(),
+            sections: plan.sections.len(),
+        }
+    }
+
+    /// Adds the sections and counts since the step started, and the error if it failed
+    pub fn finish(&mut self, running: Running, plan: &Plan, result: Result<(), String>) {
+        if let Err(error) = &result {
+            error!("{} failed: {error}", running.command);
+        }
+
+        self.steps.push(Step {
+            command: running.command,
+            duration_secs: running.started.elapsed().as_secs_f64(),
+            sections: plan.sections[running.sections..].to_vec(),
+            counts: progress::counts().since(&running.counts),
+            error: result.err(),
+        });
+        self.duration_secs = self.started_instant.elapsed().as_secs_f64();
+    }
+
+    pub fn print(&self) {
+        println!("{} in {:.1}s", style("Finished").green().bold(), self.duration_secs);
+
+        for step in &self.steps {
+            let counts = step.counts;
+            println!(
+                "{} ({:.1}s): {} media downloaded ({:.1} MB), {} skipped, {} failed",
+                style(&step.command).bold(),
+                step.duration_secs,
+                counts.media,
+                counts.bytes as f64 / 1_000_000.0,
+                counts.skipped,
+                counts.failures
+            );
+            for section in &step.sections {
+                println!("  {}", section.line());
+            }
+            if let Some(error) = &step.error {
+                println!("  {} {error}", style("FAILED").red().bold());
+            }
+        }
+    }
+
+    /// Whether a step failed
+    pub fn failed(&self) -> bool {
+        self.steps.iter().any(|step| step.error.is_some())
+    }
+
+    /// Saves the summary as `summary.json` in the out folder
+    pub fn write(&self, out: &Path) {
+        let json = serde_json::to_string_pretty(self).expect("Error serializing summary");
+        std::fs::write(out.join("summary.json"), json).expect("Error writing summary.json");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::plan::Plan;
+
+    #[test]
+    fn summary() {
+        let out = tempfile::tempdir().unwrap();
+        let mut plan = Plan::default();
+        let mut summary = super::Summary::default();
+
+        let running = summary.start("saved", &plan);
+        plan.add_posts("saved_posts", &[], out.path());
+        summary.finish(running, &plan, Ok(()));
+
+        let running = summary.start("inbox", &plan);
+        plan.add_rooms("inbox", &[], out.path());
+        summary.finish(running, &plan, Err("Reddit answered 403 Forbidden: Forbidden".to_owned()));
+
+        // Each step only lists its own folders
+        assert_eq!(summary.steps[0].sections[0].folder, "saved_posts");
+        assert_eq!(summary.steps[1].sections.len(), 1);
+        assert_eq!(summary.steps[1].sections[0].folder, "inbox");
+        assert!(summary.failed());
+
+        summary.write(out.path());
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(out.path().join("summary.json")).unwrap()).unwrap();
+        assert_eq!(json["steps"][1]["command"], "inbox");
+        assert!(json["steps"][1]["counts"]["failures"].is_u64());
+        assert!(json["steps"][0].get("error").is_none());
+        assert_eq!(json["steps"][1]["error"], "Reddit answered 403 Forbidden: Forbidden");
+    }
+}